@@ -0,0 +1,184 @@
+use std::io;
+use std::io::prelude::*;
+
+use clap::{App, Shell};
+
+use bearer::results::{BearerResult, BearerError};
+
+/// Positional argument as written by the clap generators.
+const CLIENT_NAME_BASH: &'static str = " <CLIENT_NAME>";
+const NEW_NAME_BASH: &'static str = " <NEW_NAME>";
+const CLIENT_NAME_ZSH: &'static str = "':CLIENT_NAME -- ";
+const FILES_ZSH: &'static str = ":_files' \\";
+/// Start of the section of the `register` command, whose client name is a new one.
+const REGISTER_BASH: &'static str = "bearer__register)";
+const REGISTER_ZSH: &'static str = "(register)";
+
+/// Registered clients of the config dir and of the profile given on the command line.
+///
+/// `BEARER_PROFILE` is read by `bearer list` itself.
+const CLIENTS_BASH: &'static str = r#"
+_bearer_clients() {
+    local i args=()
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "${COMP_WORDS[i]}" in
+            -c|--config|-p|--profile)
+                args+=("${COMP_WORDS[i]}" "${COMP_WORDS[i + 1]}")
+                ;;
+            --config=*|--profile=*)
+                args+=("${COMP_WORDS[i]}")
+                ;;
+        esac
+    done
+    bearer "${args[@]}" list --names 2>/dev/null
+}
+"#;
+
+const CLIENTS_ZSH: &'static str = r#"
+_bearer_clients() {
+    local i
+    local -a args clients
+    for ((i = 1; i < CURRENT; i++)); do
+        case "$words[i]" in
+            -c|--config|-p|--profile)
+                args+=("$words[i]" "$words[i + 1]")
+                ;;
+            --config=*|--profile=*)
+                args+=("$words[i]")
+                ;;
+        esac
+    done
+    clients=(${(f)"$(bearer $args list --names 2>/dev/null)"})
+    compadd -a clients
+}
+"#;
+
+const CLIENTS_FISH: &'static str = r#"
+function __bearer_clients
+    set -l tokens (commandline -opc)
+    set -l args
+    for i in (seq (count $tokens))
+        switch $tokens[$i]
+            case -c --config -p --profile
+                set args $args $tokens[$i] $tokens[(math $i + 1)]
+            case '--config=*' '--profile=*'
+                set args $args $tokens[$i]
+        end
+    end
+    bearer $args list --names 2>/dev/null
+end
+
+complete -c bearer -n "__fish_use_subcommand" -f -a "(__bearer_clients)"
+complete -c bearer -n "__fish_seen_subcommand_from refresh header edit" -f -a "(__bearer_clients)"
+complete -c bearer -n "__fish_seen_subcommand_from remove rename copy" -f -a "(__bearer_clients)"
+"#;
+
+
+/// Completion script of the app, completing the client names from the config dir.
+pub fn script(mut app: App, shell: &str) -> BearerResult<String> {
+    let generator = match shell {
+        "bash" => Shell::Bash,
+        "zsh" => Shell::Zsh,
+        "fish" => Shell::Fish,
+        _ => return Err(BearerError::ValueError(format!("Unsupported shell {}", shell))),
+    };
+    let mut buf: Vec<u8> = Vec::new();
+    app.gen_completions_to("bearer", generator, &mut buf);
+    let generated = String::from_utf8_lossy(buf.as_slice()).to_string();
+
+    let script = match generator {
+        Shell::Bash => {
+            // A new name is free text, there is nothing to offer
+            let mut register = false;
+            let generated = generated.lines()
+                .map(|line| {
+                    let line = if register {
+                        line.replace(CLIENT_NAME_BASH, "")
+                    } else {
+                        line.replace(CLIENT_NAME_BASH, " $(_bearer_clients)")
+                    };
+                    // The options follow the line of the command
+                    register = line.trim() == REGISTER_BASH;
+                    line.replace(NEW_NAME_BASH, "")
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+            format!("{}\n{}", CLIENTS_BASH.trim(), generated)
+        }
+        Shell::Zsh => {
+            let mut register = false;
+            let generated = generated.lines()
+                .map(|line| {
+                    match line.trim() {
+                        REGISTER_ZSH => register = true,
+                        ";;" => register = false,
+                        _ => {}
+                    }
+                    if line.starts_with(CLIENT_NAME_ZSH) && line.ends_with(FILES_ZSH) {
+                        let action = if register { ": " } else { ":_bearer_clients" };
+                        format!("{}{}' \\", line.trim_right_matches(FILES_ZSH), action)
+                    } else {
+                        line.to_string()
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+            // Functions must be defined after the #compdef line
+            let mut lines = generated.splitn(2, '\n');
+            let compdef = lines.next().unwrap_or("");
+            format!("{}\n{}\n{}", compdef, CLIENTS_ZSH, lines.next().unwrap_or(""))
+        }
+        _ => format!("{}\n{}", generated, CLIENTS_FISH.trim()),
+    };
+    Ok(script)
+}
+
+
+pub fn command(app: App, shell: &str) -> BearerResult<()> {
+    let script = script(app, shell)?;
+    let mut stdout = io::stdout();
+    if let Err(err) = writeln!(stdout, "{}", script) {
+        return Err(BearerError::IOError(format!("Cannot write the completion script: {}", err)));
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::app;
+
+    #[test]
+    fn test_script_bash() {
+        let bash = script(app(), "bash").unwrap();
+        assert!(bash.starts_with("_bearer_clients() {"));
+        assert!(bash.contains(" $(_bearer_clients)"));
+        assert!(!bash.contains("<CLIENT_NAME>"));
+        assert!(!bash.contains("<NEW_NAME>"));
+        assert!(bash.contains("--config=*|--profile=*)"));
+        let register = bash.lines().skip_while(|line| line.trim() != REGISTER_BASH).nth(1);
+        assert!(!register.unwrap().contains("_bearer_clients"));
+    }
+
+    #[test]
+    fn test_script_zsh() {
+        let zsh = script(app(), "zsh").unwrap();
+        assert!(zsh.starts_with("#compdef bearer\n"));
+        assert!(zsh.contains("':CLIENT_NAME -- Set the client name.:_bearer_clients' \\\n"));
+        assert!(!zsh.contains("':CLIENT_NAME -- Set the client name.:_files'"));
+        let register = zsh.lines()
+            .skip_while(|line| line.trim() != REGISTER_ZSH)
+            .take_while(|line| line.trim() != ";;")
+            .collect::<Vec<&str>>();
+        assert!(register.contains(&"':CLIENT_NAME -- Set the client name.: ' \\"));
+    }
+
+    #[test]
+    fn test_script_fish() {
+        let fish = script(app(), "fish").unwrap();
+        assert!(fish.ends_with("-f -a \"(__bearer_clients)\""));
+        assert!(fish.contains("case '--config=*' '--profile=*'"));
+        assert!(script(app(), "powershell").is_err());
+    }
+}
@@ -0,0 +1,14 @@
+use bearer::config::Config;
+use bearer::results::BearerResult;
+
+
+pub fn command(config_dir: &str, client_name: &str, new_name: &str) -> BearerResult<()> {
+    debug!("Copying client {} to {} in directory {}", client_name, new_name, config_dir);
+    let conf = Config::from_file(config_dir, client_name)?;
+    conf.copy(new_name)?.write()?;
+    println!("Client {} copied to {}, run `bearer refresh {}` to authorize it",
+             client_name,
+             new_name,
+             new_name);
+    Ok(())
+}
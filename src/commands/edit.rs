@@ -0,0 +1,69 @@
+use bearer::config::{ClientEdit, Config};
+use super::prompt::read_stdin;
+use bearer::results::{BearerResult, BearerError};
+
+
+/// Ask for the new value of a field, None keeps the current one.
+fn read_field(name: &str, current: Option<&str>) -> BearerResult<Option<String>> {
+    let message = match current {
+        Some(current) => format!("Enter the {} (current {}): ", name, current),
+        None => format!("Enter the {} (unchanged if empty): ", name),
+    };
+    let value = read_stdin(message.as_str())?;
+    match value.len() {
+        0 => Ok(None),
+        _ => Ok(Some(value)),
+    }
+}
+
+
+fn read_edit(conf: &Config) -> BearerResult<ClientEdit> {
+    let client = conf.client();
+    let scope = read_stdin(format!("Enter the scope (current {}, - to remove it): ",
+                                   client.scope.unwrap_or("none"))
+        .as_str())?;
+    Ok(ClientEdit {
+        authorize_url: read_field("OAuth2.0 Authorize Url", Some(client.authorize_url))?,
+        token_url: read_field("OAuth2.0 Token Url", Some(client.token_url))?,
+        revocation_url: read_field("OAuth2.0 Revocation Url", client.revocation_url)?,
+        client_id: read_field("Client Id", Some(client.client_id))?,
+        secret: read_field("Client Secret", None)?,
+        scope: match scope.as_str() {
+            "" => None,
+            "-" => Some(String::new()),
+            _ => Some(scope),
+        },
+    })
+}
+
+
+pub fn command(config_dir: &str,
+               client_name: &str,
+               profile: Option<&str>,
+               edit: ClientEdit,
+               keep_tokens: bool)
+               -> BearerResult<()> {
+    debug!("Editing client {} in directory {}", client_name, config_dir);
+    let mut conf = Config::from_file_with_profile(config_dir, client_name, profile)?;
+    if conf.read_only() {
+        return Err(BearerError::ValueError(format!("Client {} is read-only, run `bearer copy {} \
+                                                    <NEW_NAME>` to edit a copy of it",
+                                                   client_name,
+                                                   client_name)));
+    }
+    let edit = if edit.is_empty() {
+        read_edit(&conf)?
+    } else {
+        edit
+    };
+    let stale = conf.edit(&edit)?;
+    if !stale.is_empty() && !keep_tokens {
+        conf.clear_tokens(stale.as_slice());
+        println!("The tokens do not match the new settings and have been removed, run \
+                  `bearer refresh {}` to authorize the client again.",
+                 client_name);
+    }
+    conf.write()?;
+    println!("Client {} updated", client_name);
+    Ok(())
+}
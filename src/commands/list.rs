@@ -1,11 +1,280 @@
-use super::super::results::BearerResult;
-use super::super::helpers::path;
+use chrono::datetime::DateTime;
+use chrono::offset::utc::UTC;
+use serde_json;
 
+use bearer::config::Config;
+use bearer::results::{BearerResult, BearerError};
+use bearer::helpers::path;
 
-pub fn command(config_dir: &str) -> BearerResult<()> {
-    let clients = path::list_clients(config_dir)?;
-    for client in clients {
-        println!("{}", client);
+
+/// State of a registered client, as printed by `bearer list`.
+#[derive(Debug, Serialize)]
+pub struct ClientStatus {
+    pub name: String,
+    pub provider: Option<String>,
+    pub scope: Option<String>,
+    pub profiles: Vec<String>,
+    pub tokens: bool,
+    pub expires_at: Option<String>,
+    /// Remaining lifetime of the access token in seconds, negative once expired.
+    pub expires_in: Option<i64>,
+    pub expired: Option<bool>,
+    pub refresh_token: bool,
+    /// Set for the clients shared in the system dir.
+    pub read_only: bool,
+    /// Set if the client file cannot be loaded.
+    pub error: Option<String>,
+}
+
+impl ClientStatus {
+    pub fn from_file(config_dir: &str, client_name: &str, now: DateTime<UTC>) -> Self {
+        let mut status = ClientStatus {
+            name: client_name.to_string(),
+            provider: None,
+            scope: None,
+            profiles: Vec::new(),
+            tokens: false,
+            expires_at: None,
+            expires_in: None,
+            expired: None,
+            refresh_token: false,
+            read_only: false,
+            error: None,
+        };
+        let conf = match Config::from_file(config_dir, client_name) {
+            Ok(conf) => conf,
+            Err(err) => {
+                status.error = Some(format!("{}", err));
+                return status;
+            }
+        };
+        status.provider = Some(conf.client().provider.to_string());
+        status.scope = conf.client().scope.map(|scope| scope.to_string());
+        status.profiles = conf.profiles().iter().map(|profile| profile.to_string()).collect();
+        status.tokens = conf.access_token().is_some();
+        if let Some(expires_at) = conf.expires_at() {
+            status.expires_at = Some(expires_at.to_rfc3339());
+            status.expires_in = Some(expires_at.signed_duration_since(now).num_seconds());
+            status.expired = Some(now > expires_at);
+        }
+        status.refresh_token = conf.refresh_token().is_some();
+        status.read_only = conf.read_only();
+        status
+    }
+
+    /// Lifetime of the access token, in a human readable form.
+    fn remaining(&self) -> String {
+        match self.expires_in {
+            Some(seconds) if seconds < 0 => format!("expired {} ago", format_duration(-seconds)),
+            Some(seconds) => format_duration(seconds),
+            None => "-".to_string(),
+        }
+    }
+}
+
+
+/// Filters of the listed clients.
+#[derive(Debug, Default)]
+pub struct Filters<'a> {
+    pub expired: bool,
+    pub provider: Option<&'a str>,
+    /// Only the clients defining the profile.
+    pub profile: Option<&'a str>,
+}
+
+impl<'a> Filters<'a> {
+    fn matches(&self, status: &ClientStatus) -> bool {
+        if self.expired && status.expired != Some(true) {
+            return false;
+        }
+        if let Some(provider) = self.provider {
+            match status.provider {
+                Some(ref name) if name.to_lowercase() == provider.to_lowercase() => {}
+                _ => return false,
+            }
+        }
+        if let Some(profile) = self.profile {
+            if !status.profiles.iter().any(|name| name == profile) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+
+/// Output format of the command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Short,
+    Names,
+    Long,
+    Json,
+}
+
+
+fn format_duration(seconds: i64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds % 86400 / 3600, seconds % 3600 / 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+
+fn table(statuses: &[ClientStatus]) -> String {
+    let header = ["NAME", "PROVIDER", "SCOPE", "TOKENS", "EXPIRES AT", "REMAINING", "REFRESH"];
+    let yes_no = |val: bool| (if val { "yes" } else { "no" }).to_string();
+    let mut rows = vec![header.iter().map(|col| col.to_string()).collect::<Vec<String>>()];
+    for status in statuses {
+        if let Some(ref err) = status.error {
+            rows.push(vec![status.name.clone(), format!("invalid: {}", err)]);
+            continue;
+        }
+        rows.push(vec![status.name.clone(),
+                       status.provider.clone().unwrap_or_default(),
+                       status.scope.clone().unwrap_or_else(|| "-".to_string()),
+                       yes_no(status.tokens),
+                       status.expires_at.clone().unwrap_or_else(|| "-".to_string()),
+                       status.remaining(),
+                       yes_no(status.refresh_token)]);
+    }
+    let mut widths = vec![0; header.len()];
+    for row in rows.iter() {
+        // Only the name of an invalid client counts, its error is not aligned
+        let cols = if row.len() == header.len() { row.len() } else { 1 };
+        for (width, col) in widths.iter_mut().zip(row.iter()).take(cols) {
+            *width = (*width).max(col.len());
+        }
+    }
+    let lines = rows.iter()
+        .map(|row| {
+            let cols = row.iter()
+                .zip(widths.iter())
+                .map(|(col, &width)| format!("{:1$}", col, width))
+                .collect::<Vec<String>>();
+            cols.join("  ").trim_right().to_string()
+        })
+        .collect::<Vec<String>>();
+    lines.join("\n")
+}
+
+
+pub fn command(config_dir: &str, format: Format, filters: &Filters) -> BearerResult<()> {
+    let now: DateTime<UTC> = UTC::now();
+    let statuses = path::list_all_clients(config_dir, path::SYSTEM_CONFIG_DIR)?
+        .iter()
+        .map(|client| ClientStatus::from_file(config_dir, client.as_str(), now))
+        .filter(|status| filters.matches(status))
+        .collect::<Vec<ClientStatus>>();
+
+    match format {
+        Format::Names => {
+            for status in statuses {
+                println!("{}", status.name);
+            }
+        }
+        Format::Short => {
+            for status in statuses {
+                let mut notes = Vec::new();
+                if let Some(err) = status.error {
+                    notes.push(format!("invalid: {}", err));
+                }
+                if !status.profiles.is_empty() {
+                    notes.push(format!("profiles: {}", status.profiles.join(", ")));
+                }
+                if status.read_only {
+                    notes.push("read-only".to_string());
+                }
+                if notes.is_empty() {
+                    println!("{}", status.name);
+                } else {
+                    println!("{} ({})", status.name, notes.join("; "));
+                }
+            }
+        }
+        Format::Long => {
+            println!("{}", table(statuses.as_slice()));
+        }
+        Format::Json => {
+            match serde_json::to_string_pretty(&statuses) {
+                Ok(json) => println!("{}", json),
+                Err(err) => {
+                    return Err(BearerError::SerializationError(format!("Cannot serialize \
+                                                                         the clients: {}",
+                                                                        err)))
+                }
+            }
+        }
     }
     Ok(())
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<UTC> {
+        "2117-03-23T20:24:03+00:00".parse::<DateTime<UTC>>().unwrap()
+    }
+
+    #[test]
+    fn test_client_status() {
+        let status = ClientStatus::from_file("src/tests/conf", "dummy_with_tokens", now());
+        assert_eq!(status.provider, Some("Dummy".to_string()));
+        assert_eq!(status.tokens, true);
+        assert_eq!(status.expires_in, Some(7200));
+        assert_eq!(status.expired, Some(false));
+        assert_eq!(status.refresh_token, true);
+        assert_eq!(status.error, None);
+        assert_eq!(status.remaining(), "2h 0m");
+
+        let status = ClientStatus::from_file("src/tests/conf", "dummy", now());
+        assert_eq!(status.tokens, false);
+        assert_eq!(status.expired, None);
+        assert_eq!(status.remaining(), "-");
+
+        let status = ClientStatus::from_file("src/tests/conf", "invalid", now());
+        assert_eq!(status.provider, None);
+        assert!(status.error.is_some());
+    }
+
+    #[test]
+    fn test_filters() {
+        let later = "2117-03-25T22:24:03+00:00".parse::<DateTime<UTC>>().unwrap();
+        let status = ClientStatus::from_file("src/tests/conf", "dummy_with_tokens", later);
+        assert_eq!(status.remaining(), "expired 2d 0h ago");
+
+        let filters = Filters { expired: true, provider: Some("dummy"), profile: None };
+        assert_eq!(filters.matches(&status), true);
+        let filters = Filters { expired: false, provider: Some("Github"), profile: None };
+        assert_eq!(filters.matches(&status), false);
+
+        let status = ClientStatus::from_file("src/tests/conf", "dummy_with_tokens", now());
+        let filters = Filters { expired: true, provider: None, profile: None };
+        assert_eq!(filters.matches(&status), false);
+        assert_eq!(Filters::default().matches(&status), true);
+        let filters = Filters { profile: Some("prod"), ..Filters::default() };
+        assert_eq!(filters.matches(&status), false);
+
+        let status = ClientStatus::from_file("src/tests/conf", "dummy_with_profiles", now());
+        assert_eq!(filters.matches(&status), true);
+    }
+
+    #[test]
+    fn test_table() {
+        let statuses = vec![ClientStatus::from_file("src/tests/conf", "dummy", now()),
+                            ClientStatus::from_file("src/tests/conf", "invalid", now())];
+        let table = table(statuses.as_slice());
+        let lines = table.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "NAME     PROVIDER  SCOPE  TOKENS  EXPIRES AT  REMAINING  REFRESH");
+        assert_eq!(lines[1], "dummy    Dummy     -      no      -           -          no");
+        assert!(lines[2].starts_with("invalid  invalid: "));
+    }
+}
@@ -0,0 +1,41 @@
+use bearer::config::{Config, SCHEMA_VERSION};
+use bearer::helpers::path;
+use bearer::results::{BearerResult, BearerError};
+
+
+/// Migrate every client of the config dir to the current schema version, and move
+/// the tokens still stored in the client files to the token store.
+pub fn command(config_dir: &str) -> BearerResult<()> {
+    debug!("Migrating the clients of directory {}", config_dir);
+    let mut failures = 0;
+    for client in path::list_clients(config_dir)? {
+        let migrated = Config::from_file(config_dir, client.as_str()).and_then(|conf| {
+            if conf.has_changes() {
+                conf.write()?;
+            }
+            Ok(conf)
+        });
+        match migrated {
+            Ok(ref conf) if conf.migrated_from().is_some() => {
+                println!("{}: migrated from version {} to version {}",
+                         client,
+                         conf.migrated_from().unwrap(),
+                         SCHEMA_VERSION);
+            }
+            Ok(ref conf) if conf.has_changes() => {
+                println!("{}: tokens moved to the token store", client);
+            }
+            Ok(_) => {
+                println!("{}: up to date", client);
+            }
+            Err(err) => {
+                println!("{}: cannot migrate: {}", client, err);
+                failures += 1;
+            }
+        }
+    }
+    if failures > 0 {
+        return Err(BearerError::ValueError(format!("{} client(s) cannot be migrated", failures)));
+    }
+    Ok(())
+}
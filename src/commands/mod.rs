@@ -1,62 +1,316 @@
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
-use super::results;
+use bearer::config::ClientEdit;
+use bearer::helpers::path::default_config_dir;
+use bearer::results;
+use self::oauth2::LoginOptions;
 
+mod completions;
+mod copy;
 mod register;
 mod display_header;
+mod edit;
 mod list;
+mod migrate_dir;
+mod oauth2;
+mod prompt;
 mod refresh;
+mod remove;
+mod rename;
 
 
-pub fn start() -> results::BearerResult<()> {
+fn client_name_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("CLIENT_NAME")
+        .help("Set the client name.")
+        .required(true)
+        .index(1)
+}
+
+
+fn new_name_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("NEW_NAME")
+        .help("Set the name of the new client.")
+        .required(true)
+        .index(2)
+}
+
+
+/// Options of the `header` command, also accepted by the `bearer <client>` shortcut.
+fn header_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![Arg::with_name("SCOPE")
+             .long("scope")
+             .takes_value(true)
+             .help("Request a token with a narrowed scope, using the refresh token."),
+         Arg::with_name("AUDIENCE")
+             .long("audience")
+             .takes_value(true)
+             .help("Request a token for the given audience, using the refresh token."),
+         Arg::with_name("RESOURCE")
+             .long("resource")
+             .takes_value(true)
+             .help("Request a token for the given resource (RFC 8707), using the refresh \
+                    token.")]
+}
+
+
+/// Options of the commands that ask for a new authorization.
+fn authorize_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![Arg::with_name("MANUAL")
+             .short("m")
+             .long("manual")
+             .help("Do not start the local callback server. Paste the redirected url in the \
+                    terminal instead."),
+         Arg::with_name("PROMPT")
+             .long("prompt")
+             .takes_value(true)
+             .help("Prompt of the authorization request, such as login, consent or \
+                    select_account."),
+         Arg::with_name("LOGIN_HINT")
+             .long("login-hint")
+             .takes_value(true)
+             .help("Hint about the account to log in with."),
+         Arg::with_name("ACR_VALUES")
+             .long("acr-values")
+             .takes_value(true)
+             .help("Requested authentication context classes."),
+         Arg::with_name("MAX_AGE")
+             .long("max-age")
+             .takes_value(true)
+             .validator(|val| match val.parse::<u64>() {
+                 Ok(_) => Ok(()),
+                 Err(_) => Err("max-age must be a number of seconds".to_string()),
+             })
+             .help("Maximum age of the authentication in seconds."),
+         Arg::with_name("UI_LOCALES")
+             .long("ui-locales")
+             .takes_value(true)
+             .help("Preferred languages of the login pages.")]
+}
+
+
+fn login_options<'a>(matches: &'a ArgMatches) -> LoginOptions<'a> {
+    LoginOptions {
+        prompt: matches.value_of("PROMPT"),
+        login_hint: matches.value_of("LOGIN_HINT"),
+        acr_values: matches.value_of("ACR_VALUES"),
+        max_age: matches.value_of("MAX_AGE"),
+        ui_locales: matches.value_of("UI_LOCALES"),
+    }
+}
+
+
+fn client_edit(matches: &ArgMatches) -> ClientEdit {
+    let value = |name: &str| matches.value_of(name).map(|val| val.to_string());
+    ClientEdit {
+        authorize_url: value("AUTHORIZE_URL"),
+        token_url: value("TOKEN_URL"),
+        revocation_url: value("REVOCATION_URL"),
+        client_id: value("CLIENT_ID"),
+        secret: value("SECRET"),
+        scope: value("SCOPE"),
+    }
+}
+
+
+/// Value of a global option, given before or after the command name.
+fn global_value<'a>(matches: &'a ArgMatches,
+                    command: Option<&'a ArgMatches>,
+                    name: &str)
+                    -> Option<&'a str> {
+    command.and_then(|command| command.value_of(name)).or(matches.value_of(name))
+}
 
 
-    let matches = App::new("bearer")
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("bearer")
         .version("0.2.3")
         .author("Guillaume Gauvrit <guillaume@gauvr.it>")
         .about("Create Bearer Token from the command line")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .setting(AppSettings::VersionlessSubcommands)
+        .after_help("`bearer <CLIENT_NAME>` is a shortcut for `bearer header <CLIENT_NAME>`.")
         .arg(Arg::with_name("CONFIG")
             .short("c")
-            .default_value("~/.config/bearer")
-            .help("Set a custom config directory. Contains One file per client."))
-        .arg(Arg::with_name("LIST")
-            .short("l")
-            .long("list")
-            .conflicts_with("REGISTER")
-            .help("List registered clients."))
-        .arg(Arg::with_name("REGISTER")
-            .short("r")
-            .long("register")
-            .conflicts_with("LIST")
-            .help("Register a new client. This command is interactive."))
-        .arg(Arg::with_name("REFRESH")
-            .short("u")
-            .long("refresh")
-            .conflicts_with("LIST")
-            .help("Refresh an existing client. This command is interactive."))
-        .arg(Arg::with_name("CLIENT_NAME")
-            .help("Set the client name.")
-            .required_unless_one(&["LIST"])
-            .index(1))
-        .get_matches();
-
-
-    // Gets a value for config if supplied by user, or defaults to "default.conf"
-
-    let config_dir = matches.value_of("CONFIG").unwrap();
-    let client_name = matches.value_of("CLIENT_NAME".to_string());
+            .long("config")
+            .takes_value(true)
+            .global(true)
+            .help("Set a custom config directory. Contains One file per client. [default: \
+                   $BEARER_CONFIG_DIR, $XDG_CONFIG_HOME/bearer or ~/.config/bearer]"))
+        .arg(Arg::with_name("PROFILE")
+            .short("p")
+            .long("profile")
+            .takes_value(true)
+            .global(true)
+            .env("BEARER_PROFILE")
+            .help("Use a profile of the client, defined in its [profiles.<name>] section."))
+        .args(header_args().as_slice())
+        .arg(client_name_arg())
+        .subcommand(SubCommand::with_name("register")
+            .about("Register a new client. This command is interactive.")
+            .args(authorize_args().as_slice())
+            .arg(client_name_arg()))
+        .subcommand(SubCommand::with_name("list")
+            .about("List registered clients.")
+            .arg(Arg::with_name("NAMES")
+                .long("names")
+                .conflicts_with_all(&["LONG", "JSON"])
+                .help("Only print the client names, one per line."))
+            .arg(Arg::with_name("LONG")
+                .short("l")
+                .long("long")
+                .conflicts_with("JSON")
+                .help("Print the provider, the scope and the state of the tokens."))
+            .arg(Arg::with_name("JSON")
+                .long("json")
+                .help("Print the state of the clients as JSON."))
+            .arg(Arg::with_name("EXPIRED")
+                .long("expired")
+                .help("Only list the clients whose access token has expired."))
+            .arg(Arg::with_name("PROVIDER")
+                .long("provider")
+                .takes_value(true)
+                .help("Only list the clients of the given provider.")))
+        .subcommand(SubCommand::with_name("refresh")
+            .about("Refresh an existing client. This command is interactive.")
+            .args(authorize_args().as_slice())
+            .arg(client_name_arg()))
+        .subcommand(SubCommand::with_name("header")
+            .about("Print the Authorization header of a client.")
+            .args(header_args().as_slice())
+            .arg(client_name_arg()))
+        .subcommand(SubCommand::with_name("remove")
+            .about("Remove a registered client and its tokens.")
+            .arg(Arg::with_name("REVOKE")
+                .long("revoke")
+                .help("Revoke the tokens at the revocation_url of the client first."))
+            .arg(client_name_arg()))
+        .subcommand(SubCommand::with_name("edit")
+            .about("Edit the registration of a client. This command is interactive if no \
+                    setting is given.")
+            .arg(Arg::with_name("AUTHORIZE_URL")
+                .long("authorize-url")
+                .takes_value(true)
+                .help("Set the authorize url, the tokens are removed."))
+            .arg(Arg::with_name("TOKEN_URL")
+                .long("token-url")
+                .takes_value(true)
+                .help("Set the token url, the tokens are removed."))
+            .arg(Arg::with_name("REVOCATION_URL")
+                .long("revocation-url")
+                .takes_value(true)
+                .help("Set the token revocation url."))
+            .arg(Arg::with_name("CLIENT_ID")
+                .long("client-id")
+                .takes_value(true)
+                .help("Set the client id, the tokens are removed."))
+            .arg(Arg::with_name("SECRET")
+                .long("secret")
+                .takes_value(true)
+                .help("Set the client secret, the tokens are kept."))
+            .arg(Arg::with_name("SCOPE")
+                .long("scope")
+                .takes_value(true)
+                .help("Set the scope, an empty scope removes it. The tokens are removed."))
+            .arg(Arg::with_name("KEEP_TOKENS")
+                .long("keep-tokens")
+                .help("Keep the tokens, even if they do not match the new settings."))
+            .arg(client_name_arg()))
+        .subcommand(SubCommand::with_name("rename")
+            .about("Rename a registered client, with its tokens.")
+            .arg(client_name_arg())
+            .arg(new_name_arg()))
+        .subcommand(SubCommand::with_name("copy")
+            .about("Copy the registration of a client to a new name, without its tokens.")
+            .arg(client_name_arg())
+            .arg(new_name_arg()))
+        .subcommand(SubCommand::with_name("migrate-dir")
+            .about("Migrate the files of the config directory to the current format. The \
+                    original files are kept with a .bak extension."))
+        .subcommand(SubCommand::with_name("completions")
+            .about("Print the completion script of a shell, client names included.")
+            .arg(Arg::with_name("SHELL")
+                .possible_values(&["bash", "zsh", "fish"])
+                .required(true)
+                .index(1)))
+}
+
+
+pub fn start() -> results::BearerResult<()> {
+
+    let matches = app().get_matches();
+
+    let command = matches.subcommand().1;
+    let default_config_dir = default_config_dir();
+    let config_dir = global_value(&matches, command, "CONFIG")
+        .unwrap_or(default_config_dir.as_str());
+    let profile = global_value(&matches, command, "PROFILE");
+    let client_name = command.unwrap_or(&matches).value_of("CLIENT_NAME");
 
     debug!("config_dir: {:?}", config_dir);
     debug!("client_name: {:?}", client_name);
 
-    if matches.is_present("LIST") {
-        list::command(config_dir)?;
-    } else if matches.is_present("REGISTER") {
-        register::command(config_dir, client_name.unwrap())?;
-    } else if matches.is_present("REFRESH") {
-        refresh::command(config_dir, client_name.unwrap())?;
-    } else {
-        display_header::command(config_dir, client_name.unwrap())?;
+    match matches.subcommand() {
+        ("register", Some(args)) => {
+            register::command(config_dir,
+                              client_name.unwrap(),
+                              args.is_present("MANUAL"),
+                              login_options(args))?;
+        }
+        ("list", Some(args)) => {
+            let format = if args.is_present("NAMES") {
+                list::Format::Names
+            } else if args.is_present("JSON") {
+                list::Format::Json
+            } else if args.is_present("LONG") {
+                list::Format::Long
+            } else {
+                list::Format::Short
+            };
+            let filters = list::Filters {
+                expired: args.is_present("EXPIRED"),
+                provider: args.value_of("PROVIDER"),
+                profile,
+            };
+            list::command(config_dir, format, &filters)?;
+        }
+        ("refresh", Some(args)) => {
+            refresh::command(config_dir,
+                             client_name.unwrap(),
+                             profile,
+                             args.is_present("MANUAL"),
+                             login_options(args))?;
+        }
+        ("remove", Some(args)) => {
+            remove::command(config_dir, client_name.unwrap(), args.is_present("REVOKE"))?;
+        }
+        ("edit", Some(args)) => {
+            edit::command(config_dir,
+                          client_name.unwrap(),
+                          profile,
+                          client_edit(args),
+                          args.is_present("KEEP_TOKENS"))?;
+        }
+        ("rename", Some(args)) => {
+            rename::command(config_dir, client_name.unwrap(), args.value_of("NEW_NAME").unwrap())?;
+        }
+        ("copy", Some(args)) => {
+            copy::command(config_dir, client_name.unwrap(), args.value_of("NEW_NAME").unwrap())?;
+        }
+        ("migrate-dir", Some(_)) => {
+            migrate_dir::command(config_dir)?;
+        }
+        ("completions", Some(args)) => {
+            completions::command(app(), args.value_of("SHELL").unwrap())?;
+        }
+        (_, args) => {
+            let args = args.unwrap_or(&matches);
+            display_header::command(config_dir,
+                                    client_name.unwrap(),
+                                    profile,
+                                    args.value_of("SCOPE"),
+                                    args.value_of("AUDIENCE"),
+                                    args.value_of("RESOURCE"))?;
+        }
     }
     Ok(())
 }
@@ -0,0 +1,709 @@
+use std::time::Duration;
+use std::io::Write;
+use std::io::prelude::*;
+use std::net::{TcpListener, TcpStream};
+
+use ring::rand::{SecureRandom, SystemRandom};
+use url::Url;
+use url::form_urlencoded;
+
+use bearer::config::{Tokens, Config, ClientRef};
+use bearer::results::{BearerResult, BearerError, OAuth2ErrorResponse};
+use bearer::helpers::oauth2client;
+use super::prompt::read_stdin;
+
+/// Parameters of the authorization request set by bearer, never by the `authorize_params`.
+const RESERVED_AUTHORIZE_PARAMS: [&'static str; 5] = ["response_type", "client_id",
+                                                       "redirect_uri", "scope", "state"];
+
+fn url_encode(to_encode: &str) -> String {
+    to_encode.as_bytes().iter().fold(String::new(), |mut out, &b| {
+        match b as char {
+            // unreserved:
+            'A'...'Z' | 'a'...'z' | '0'...'9' | '-' | '.' | '_' | '~' => out.push(b as char),
+
+            ' ' => out.push('+'),
+
+            ch => out.push_str(format!("%{:02X}", ch as usize).as_str()),
+        };
+
+        out
+    })
+}
+
+
+/// Options of the authorization request for a single login, see OpenID Connect Core 3.1.2.1.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LoginOptions<'a> {
+    pub prompt: Option<&'a str>,
+    pub login_hint: Option<&'a str>,
+    pub acr_values: Option<&'a str>,
+    pub max_age: Option<&'a str>,
+    pub ui_locales: Option<&'a str>,
+}
+
+impl<'a> LoginOptions<'a> {
+    fn params(&self) -> Vec<(&'static str, &'a str)> {
+        let options = [("prompt", self.prompt),
+                       ("login_hint", self.login_hint),
+                       ("acr_values", self.acr_values),
+                       ("max_age", self.max_age),
+                       ("ui_locales", self.ui_locales)];
+        options.iter()
+            .filter_map(|&(name, value)| value.map(|value| (name, value)))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.params().is_empty()
+    }
+}
+
+
+struct Http<'a> {
+    port: usize,
+    client: ClientRef<'a>,
+    login: LoginOptions<'a>,
+    state: String,
+    tokens: Option<BearerResult<Tokens>>,
+}
+
+impl<'a> Http<'a> {
+    pub fn new(config: &'a Config, port: usize, login: LoginOptions<'a>, state: String) -> Self {
+        Http {
+            port,
+            client: config.client(),
+            login,
+            state,
+            tokens: None,
+        }
+    }
+
+    pub fn fetch_tokens(&mut self) -> BearerResult<Tokens> {
+        let listener = TcpListener::bind(self.addr().as_str()).unwrap();
+
+
+        while self.tokens.is_none() {
+            let stream = listener.incoming().next().unwrap();
+            let mut stream = stream.unwrap();
+            self.handle_client(&mut stream);
+        }
+        let tokens = self.tokens.as_ref().unwrap();
+        match tokens.as_ref() {
+            Ok(tokens) => Ok(tokens.clone()),
+            Err(err) => Err(err.clone()),
+        }
+    }
+
+    fn handle_client(&mut self, stream: &mut TcpStream) {
+        stream.set_read_timeout(Some(Duration::new(15, 0))).unwrap();
+        let mut buffer = [0; 4096];
+        stream.read(&mut buffer[..]).unwrap();
+        let httpquery = String::from_utf8_lossy(&buffer);
+        // debug!("{}", string);
+
+        // We don't bother the header
+        let httpquery = httpquery.lines().next().unwrap();
+        // debug!("{}", httpquery);
+
+        let mut httpquery = httpquery.split_whitespace();
+        let verb = httpquery.next().unwrap();
+        if verb != "GET" {
+            self.handle_405(stream);
+            return;
+        }
+
+        let path = httpquery.next().unwrap();
+        let mut path = path.split("?");
+        let pathinfo = path.next().unwrap();
+
+        if pathinfo != "/callback" {
+            self.handle_404(stream);
+            return;
+        }
+
+        let querystring = path.next();
+        if querystring.is_none() {
+            self.handle_302(stream);
+            return;
+        }
+
+        let mut code = None;
+        let mut state = None;
+        let mut error = None;
+        let mut error_description = None;
+        for (key, value) in form_urlencoded::parse(querystring.unwrap().as_bytes()) {
+            match &*key {
+                "error" => error = Some(value.into_owned()),
+                "error_description" => error_description = Some(value.into_owned()),
+                "code" => code = Some(value.into_owned()),
+                "state" => state = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+        // Ignore callbacks that do not come from our authorization request
+        if state.as_ref().map(|st| st.as_str()) != Some(self.state.as_str()) {
+            self.handle_400(stream, "State mismatch");
+            return;
+        }
+        if let Some(error) = error {
+            let error = OAuth2ErrorResponse::new(error.as_str(),
+                                                 error_description.as_ref()
+                                                     .map(|desc| desc.as_str()));
+            self.handle_200_error(stream, error);
+        } else if let Some(code) = code {
+            self.handle_200_code(stream, code.as_str());
+        } else {
+            self.handle_400(stream, "No authorization code");
+        }
+    }
+
+    fn handle_400(&mut self, stream: &mut TcpStream, message: &str) {
+        let content = format!("{}\n", message);
+        let resp = format!("HTTP/1.1 400 Bad Request
+Connection: close
+Server: bearer-rs
+Content-Type: text/plain;charset=UTF-8
+Content-Length: {}
+
+{}",
+                           content.len(),
+                           content);
+        stream.write(resp.as_bytes()).unwrap();
+    }
+
+    fn handle_404(&mut self, stream: &mut TcpStream) {
+        stream.write(b"HTTP/1.1 404 Not Found
+Connection: close
+Server: bearer-rs
+Content-Type: text/plain;charset=UTF-8
+Content-Length: 10
+
+Not Found
+")
+            .unwrap();
+    }
+
+    fn handle_405(&mut self, stream: &mut TcpStream) {
+        stream.write(b"HTTP/1.1 405 Method Not Allowed
+Connection: close
+Server: bearer-rs
+Content-Type: text/plain;charset=UTF-8
+Content-Length: 19
+
+Method Not Allowed
+")
+            .unwrap();
+    }
+
+    fn handle_302(&mut self, stream: &mut TcpStream) {
+
+
+        let location = authorize_url(&self.client,
+                                     self.redirect_uri().as_str(),
+                                     &self.login,
+                                     Some(self.state.as_str()));
+        debug!("Redirecting to {}", location);
+
+        let resp = format!("HTTP/1.1 302 Moved Temporarily
+Connection: close
+Server: bearer-rs
+Location: {}
+",
+                           location);
+        stream.write(resp.as_bytes()).unwrap();
+    }
+
+    fn handle_200_code(&mut self, stream: &mut TcpStream, code: &str) {
+        debug!("OAuth2.0 Authorization Code received, fetching tokens");
+
+        let tokens = oauth2client::from_authcode(&self.client, code, self.redirect_uri().as_str());
+        let content = match tokens {
+            Ok(res) => {
+                self.tokens = Some(Ok(res));
+                "Token received".to_string()
+            }
+            Err(err) => format!("Error while fetching token: {:?}", err),
+        };
+
+        let resp = format!("HTTP/1.1 200 Ok
+Connection: close
+Server: bearer-rs
+Content-Type: text/plain;charset=UTF-8
+Content-Length: {}
+
+{}",
+                           content.len(),
+                           content);
+
+        stream.write(resp.as_bytes()).unwrap();
+
+
+    }
+
+    fn handle_200_error(&mut self, stream: &mut TcpStream, error: OAuth2ErrorResponse) {
+        let content = format!("No Tokens returns. OAuth2.0 Authorization Server Error: {}",
+                              error.error);
+        let resp = format!("HTTP/1.1 200 Ok
+Connection: close
+Server: bearer-rs
+Content-Type: text/plain;charset=UTF-8
+Content-Length: {}
+
+{}",
+                           content.len(),
+                           content);
+
+        stream.write(resp.as_bytes()).unwrap();
+        self.tokens = Some(Err(BearerError::OAuth2Error(error)));
+
+    }
+
+    fn addr(&self) -> String {
+        return format!("localhost:{}", self.port);
+    }
+
+    fn redirect_uri(&self) -> String {
+        return format!("http://localhost:{}/callback", self.port);
+    }
+}
+
+
+pub fn authorize_url(client: &ClientRef,
+                     redirect_uri: &str,
+                     login: &LoginOptions,
+                     state: Option<&str>)
+                     -> String {
+    let mut location = format!("{}?response_type=code&client_id={}&redirect_uri={}",
+                               client.authorize_url,
+                               url_encode(client.client_id),
+                               url_encode(redirect_uri));
+
+    if let Some(scope) = client.scope {
+        location.push_str("&scope=");
+        location.push_str(url_encode(scope).as_str());
+    }
+    if let Some(resources) = client.resources {
+        for resource in resources.iter() {
+            location.push_str("&resource=");
+            location.push_str(url_encode(resource).as_str());
+        }
+    }
+    let login = login.params();
+    if let Some(params) = client.authorize_params {
+        // The options of the login replace the ones of the client.
+        for (key, value) in params.iter() {
+            if RESERVED_AUTHORIZE_PARAMS.contains(&key.as_str()) {
+                warn!("Ignoring the authorize_params {}, it is set by bearer", key);
+            } else if !login.iter().any(|&(name, _)| name == key.as_str()) {
+                location.push_str(format!("&{}={}", url_encode(key), url_encode(value)).as_str());
+            }
+        }
+    }
+    for &(key, value) in login.iter() {
+        location.push_str(format!("&{}={}", key, url_encode(value)).as_str());
+    }
+    if let Some(state) = state {
+        location.push_str("&state=");
+        location.push_str(url_encode(state).as_str());
+    }
+    location
+}
+
+
+/// Unguessable value binding the callback to the authorization request.
+fn generate_state() -> BearerResult<String> {
+    let mut state = [0u8; 16];
+    if SystemRandom::new().fill(&mut state).is_err() {
+        return Err(BearerError::ValueError("Cannot generate a random state".to_string()));
+    }
+    Ok(state.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+
+/// Extract the authorization code from what the user pasted in the terminal.
+///
+/// The input may be the full redirected url or its query string, a bare code is refused
+/// since its state cannot be checked.
+fn parse_callback_input(input: &str, state: &str) -> BearerResult<String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(BearerError::ValueError("No authorization code provided".to_string()));
+    }
+
+    let querystring = if input.contains("://") {
+        match Url::parse(input) {
+            Ok(url) => url.query().unwrap_or("").to_string(),
+            Err(err) => {
+                return Err(BearerError::ValueError(format!("Cannot parse url {}: {}",
+                                                           input,
+                                                           err)))
+            }
+        }
+    } else if input.contains('=') {
+        input.trim_left_matches('?').to_string()
+    } else {
+        return Err(BearerError::ValueError("Paste the full url, a bare code cannot be checked \
+against the state of the authorization request"
+            .to_string()));
+    };
+
+    let mut code = None;
+    let mut received_state = None;
+    let mut error = None;
+    let mut error_description = None;
+    for (key, value) in form_urlencoded::parse(querystring.as_bytes()) {
+        match &*key {
+            "code" => code = Some(value.into_owned()),
+            "state" => received_state = Some(value.into_owned()),
+            "error" => error = Some(value.into_owned()),
+            "error_description" => error_description = Some(value.into_owned()),
+            _ => {}
+        }
+    }
+
+    if let Some(error) = error {
+        return Err(BearerError::OAuth2Error(OAuth2ErrorResponse::new(error.as_str(),
+                                                                     error_description.as_ref()
+                                                                         .map(|desc| {
+                                                                             desc.as_str()
+                                                                         }))));
+    }
+    if received_state.as_ref().map(|st| st.as_str()) != Some(state) {
+        return Err(BearerError::ValueError("State mismatch, the url does not come from this \
+authorization request"
+            .to_string()));
+    }
+    match code {
+        Some(code) => Ok(code),
+        None => Err(BearerError::ValueError("No authorization code found in the url".to_string())),
+    }
+}
+
+
+pub fn get_tokens<'a>(config: &'a Config,
+                      port: usize,
+                      login: LoginOptions<'a>)
+                      -> BearerResult<Tokens> {
+
+    let mut server: Http<'a> = Http::new(config, port, login, generate_state()?);
+    let token = server.fetch_tokens()?;
+    Ok(token)
+}
+
+
+/// Retrieve the tokens without listening on the callback port.
+///
+/// Used when the browser cannot reach the machine running bearer, e.g. over ssh.
+pub fn get_tokens_manually(config: &Config,
+                           port: usize,
+                           login: LoginOptions)
+                           -> BearerResult<Tokens> {
+    let client = config.client();
+    let redirect_uri = format!("http://localhost:{}/callback", port);
+    let state = generate_state()?;
+
+    println!("");
+    println!("Visit the following url to finish the configuration:");
+    println!("");
+    println!("{}",
+             authorize_url(&client, redirect_uri.as_str(), &login, Some(state.as_str())));
+    println!("");
+    println!("Your browser will fail to load the page it is redirected to after login.");
+    let input = read_stdin("Paste the url of that page: ")?;
+    let code = parse_callback_input(input.as_str(), state.as_str())?;
+
+    debug!("OAuth2.0 Authorization Code received, fetching tokens");
+    oauth2client::from_authcode(&client, code.as_str(), redirect_uri.as_str())
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time;
+    use std::net::TcpStream;
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use super::super::super::test_server::serve;
+    use bearer::results::BearerError;
+
+    #[test]
+    fn test_url_encode() {
+        assert_eq!(url_encode("The éêè !"), "The+%C3%A9%C3%AA%C3%A8+%21")
+    }
+
+    #[test]
+    fn test_authorize_url() {
+        let conf = Config::from_file("src/tests/conf", "dummy").unwrap();
+        assert_eq!(authorize_url(&conf.client(),
+                                 "http://localhost:6750/callback",
+                                 &LoginOptions::default(),
+                                 Some("x y")),
+                   "http://127.0.0.1:1337/authorize?response_type=code&client_id=129eff26&\
+redirect_uri=http%3A%2F%2Flocalhost%3A6750%2Fcallback&state=x+y");
+    }
+
+    #[test]
+    fn test_authorize_url_resources() {
+        let resources = vec!["https://a.example/".to_string(), "urn:b".to_string()];
+        let client = ClientRef {
+            authorize_url: "https://idp/authorize",
+            client_id: "cid",
+            resources: Some(resources.as_slice()),
+            ..ClientRef::default()
+        };
+        assert!(authorize_url(&client, "http://localhost/", &LoginOptions::default(), None)
+            .ends_with("&resource=https%3A%2F%2Fa.example%2F&resource=urn%3Ab"));
+    }
+
+    #[test]
+    fn test_authorize_url_extra_params() {
+        let mut params = BTreeMap::new();
+        params.insert("audience".to_string(), "https://api/".to_string());
+        params.insert("prompt".to_string(), "consent".to_string());
+        let client = ClientRef {
+            authorize_url: "https://idp/authorize",
+            client_id: "cid",
+            scope: Some("openid https://api/read"),
+            authorize_params: Some(&params),
+            ..ClientRef::default()
+        };
+        assert_eq!(authorize_url(&client,
+                                 "http://localhost:6750/callback",
+                                 &LoginOptions::default(),
+                                 None),
+                   "https://idp/authorize?response_type=code&client_id=cid&\
+redirect_uri=http%3A%2F%2Flocalhost%3A6750%2Fcallback&scope=openid+https%3A%2F%2Fapi%2Fread&\
+audience=https%3A%2F%2Fapi%2F&prompt=consent");
+
+        let login = LoginOptions {
+            prompt: Some("select_account"),
+            login_hint: Some("me@example.com"),
+            max_age: Some("0"),
+            ..LoginOptions::default()
+        };
+        assert_eq!(authorize_url(&client, "http://localhost:6750/callback", &login, None),
+                   "https://idp/authorize?response_type=code&client_id=cid&\
+redirect_uri=http%3A%2F%2Flocalhost%3A6750%2Fcallback&scope=openid+https%3A%2F%2Fapi%2Fread&\
+audience=https%3A%2F%2Fapi%2F&prompt=select_account&login_hint=me%40example.com&max_age=0");
+    }
+
+    #[test]
+    fn test_authorize_url_reserved_params() {
+        let mut params = BTreeMap::new();
+        for name in RESERVED_AUTHORIZE_PARAMS.iter() {
+            params.insert(name.to_string(), "evil".to_string());
+        }
+        params.insert("audience".to_string(), "api".to_string());
+        let client = ClientRef {
+            authorize_url: "https://idp/authorize",
+            client_id: "cid",
+            authorize_params: Some(&params),
+            ..ClientRef::default()
+        };
+        assert_eq!(authorize_url(&client,
+                                 "http://localhost:6750/callback",
+                                 &LoginOptions::default(),
+                                 Some("st")),
+                   "https://idp/authorize?response_type=code&client_id=cid&\
+redirect_uri=http%3A%2F%2Flocalhost%3A6750%2Fcallback&audience=api&state=st");
+    }
+
+    #[test]
+    fn test_parse_callback_input() {
+        assert_eq!(parse_callback_input("http://localhost:6750/callback?code=a%2Fc&state=st",
+                                        "st")
+                       .ok(),
+                   Some("a/c".to_string()));
+        assert_eq!(parse_callback_input("code=abc&state=st", "st").ok(),
+                   Some("abc".to_string()));
+    }
+
+    #[test]
+    fn test_parse_callback_input_error() {
+        assert!(matches!(parse_callback_input("", "st"), Err(BearerError::ValueError(_))));
+        assert!(matches!(parse_callback_input(" abc \n", "st"), Err(BearerError::ValueError(_))));
+        assert!(matches!(parse_callback_input("http://localhost:6750/callback?code=abc&state=xx",
+                                              "st"),
+                         Err(BearerError::ValueError(_))));
+        assert!(matches!(parse_callback_input("http://localhost:6750/callback\
+?error=access_denied&state=st",
+                                              "st"),
+                         Err(BearerError::OAuth2Error(_))));
+    }
+
+    /// State of the authorization request in a redirect of the callback server.
+    fn location_state(response: &str) -> String {
+        let location = response.lines().find(|line| line.starts_with("Location: ")).unwrap();
+        let url = Url::parse(location.trim_left_matches("Location: ")).unwrap();
+        url.query_pairs().find(|&(ref key, _)| key == "state").unwrap().1.into_owned()
+    }
+
+    #[test]
+    fn test_generate_state() {
+        let state = generate_state().unwrap();
+        assert_eq!(state.len(), 32);
+        assert!(state.chars().all(|c| c.is_digit(16)));
+        assert!(generate_state().unwrap() != state);
+    }
+
+    /// A free port of localhost, for the callback server to bind.
+    fn free_port() -> usize {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port() as usize
+    }
+
+    /// Connect to the callback server, once it listens on localhost.
+    fn connect_callback_server(port: usize) -> (String, TcpStream) {
+        for _ in 0..500 {
+            for addr in &[format!("127.0.0.1:{}", port), format!("[::1]:{}", port)] {
+                if let Ok(stream) = TcpStream::connect(addr.as_str()) {
+                    return (addr.to_string(), stream);
+                }
+            }
+            thread::sleep(time::Duration::from_millis(10));
+        }
+        panic!("The callback server does not listen on port {}", port);
+    }
+
+    #[test]
+    fn test_get_tokens_ok() {
+        let (authorization_server_port, authservhandler) = serve(move |authorization_server| {
+            let stream = authorization_server.incoming().next().unwrap();
+            let mut stream = stream.unwrap();
+            let tokens = r#"{"access_token": "atok",
+"expires_in": 42,
+"refresh_token": "rtok"}"#;
+            let content_len = format!("Content-Length: {}", tokens.len());
+            let resp = vec!["HTTP/1.0 200 Ok",
+                            "Content-Type: application/json",
+                            content_len.as_str(),
+                            "",
+                            tokens];
+            let resp = resp.join("\r\n");
+
+            stream.write(resp.as_bytes()).unwrap();
+        });
+        let client_port = free_port();
+        let httphandler = thread::spawn(move || {
+            let authorize = format!("http://127.0.0.1:{}/authorize", authorization_server_port);
+            let token = format!("http://127.0.0.1:{}/token", authorization_server_port);
+            let conf = Config::new("/tmp",
+                                   "client_name",
+                                   "provider",
+                                   authorize.as_str(),
+                                   token.as_str(),
+                                   "12e26",
+                                   "secret",
+                                   None)
+                .unwrap();
+
+            let tokens = get_tokens(&conf, client_port, LoginOptions::default());
+            assert_eq!(tokens.is_ok(), true);
+            let tokens = tokens.unwrap();
+            assert_eq!(tokens.access_token, "atok");
+            assert_eq!(tokens.refresh_token.unwrap(), "rtok");
+            // assert_eq!(tokens.expires_at, "TIME DEPENDANT VALUE");
+        });
+
+        let (client_addr, mut client) = connect_callback_server(client_port);
+        client.write_all(b"GET /callback HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        let state = location_state(response.as_str());
+        assert_eq!(state.len(), 32);
+        assert_eq!(response, format!(r#"HTTP/1.1 302 Moved Temporarily
+Connection: close
+Server: bearer-rs
+Location: http://127.0.0.1:{}/authorize?response_type=code&client_id=12e26&redirect_uri=http%3A%2F%2Flocalhost%3A{}%2Fcallback&state={}
+"#, authorization_server_port, client_port, state));
+
+        // A callback with another state is not ours
+        let mut client = TcpStream::connect(client_addr.as_str()).unwrap();
+        client.write_all(b"GET /callback?code=forged&state=xx HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\n"));
+        assert!(response.ends_with("\n\nState mismatch\n"));
+
+        // A callback with neither a code nor an error gets an answer too
+        let mut client = TcpStream::connect(client_addr.as_str()).unwrap();
+        let callback = format!("GET /callback?state={} HTTP/1.1\r\n\r\n", state);
+        client.write_all(callback.as_bytes()).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\n"));
+        assert!(response.ends_with("\n\nNo authorization code\n"));
+
+        let mut client = TcpStream::connect(client_addr).unwrap();
+        let callback = format!("GET /callback?code=abc&state={} HTTP/1.1\r\n\r\n", state);
+        client.write_all(callback.as_bytes()).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+
+        assert_eq!(response,
+                   r#"HTTP/1.1 200 Ok
+Connection: close
+Server: bearer-rs
+Content-Type: text/plain;charset=UTF-8
+Content-Length: 14
+
+Token received"#);
+
+        // ensure threads are terminated
+        httphandler.join().unwrap();
+        authservhandler.join().unwrap();
+
+    }
+
+    #[test]
+    fn test_get_tokens_error() {
+
+        let client_port = free_port();
+
+        let httphandler = thread::spawn(move || {
+            let conf = Config::from_file("src/tests/conf", "dummy").unwrap();
+            let tokens = get_tokens(&conf, client_port, LoginOptions::default());
+            assert_eq!(tokens.is_err(), true);
+            let err = tokens.unwrap_err();
+            match err {
+                BearerError::OAuth2Error(ref err) => {
+                    assert_eq!(err.error, "server_error");
+                    assert_eq!(err.error_description,
+                               Some("internal server error".to_string()));
+                }
+                _ => panic!("Unexpected error {:?}", err),
+            }
+        });
+
+        let (client_addr, mut client) = connect_callback_server(client_port);
+        client.write_all(b"GET /callback HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        let state = location_state(response.as_str());
+
+        let mut client = TcpStream::connect(client_addr.as_str()).unwrap();
+        let callback = format!("GET /callback?error=server_error&error_description=internal+\
+server+error&state={} HTTP/1.1\r\n\r\n",
+                               state);
+        client.write_all(callback.as_bytes()).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+
+        assert_eq!(response,
+                   r#"HTTP/1.1 200 Ok
+Connection: close
+Server: bearer-rs
+Content-Type: text/plain;charset=UTF-8
+Content-Length: 68
+
+No Tokens returns. OAuth2.0 Authorization Server Error: server_error"#);
+
+        // ensure threads are terminated
+        httphandler.join().unwrap();
+    }
+
+}
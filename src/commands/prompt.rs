@@ -0,0 +1,16 @@
+use std::io;
+use std::io::prelude::*;
+
+use bearer::results::{BearerResult, BearerError};
+
+
+pub fn read_stdin(message: &str) -> BearerResult<String> {
+    print!("{}", message);
+    io::stdout().flush().unwrap();
+
+    let mut buffer = String::new();
+    match io::stdin().read_line(&mut buffer) {
+        Ok(_) => Ok(buffer.trim().to_string()),
+        Err(err) => Err(BearerError::IOError(format!("{}", err))),
+    }
+}
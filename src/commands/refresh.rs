@@ -1,21 +1,47 @@
 
-use super::super::config::Config;
-use super::super::helpers::oauth2;
-use super::super::helpers::oauth2client;
-use super::super::results::BearerResult;
+use bearer::config::{Config, TokenKey};
+use super::oauth2;
+use super::oauth2::LoginOptions;
+use bearer::helpers::oauth2client;
+use bearer::results::{BearerResult, BearerError};
 
-pub fn command(config_dir: &str, client_name: &str) -> BearerResult<()> {
+pub fn command(config_dir: &str,
+               client_name: &str,
+               profile: Option<&str>,
+               manual: bool,
+               login: LoginOptions)
+               -> BearerResult<()> {
 
     debug!("Refresh existing client {} in directory {}",
            client_name,
            config_dir);
 
-    let mut conf = Config::from_file(config_dir, client_name)?;
+    let mut conf = Config::from_file_with_profile(config_dir, client_name, profile)?;
 
     let tokens = match conf.refresh_token() {
-        Some(rtoken) => {
-            let tokens = oauth2client::from_refresh_token(&conf.client(), rtoken)?;
-            debug!("Token retrieved usgin refresh token: {:?}", tokens);
+        // The login options only apply to a new authorization
+        Some(rtoken) if login.is_empty() => {
+            let key = TokenKey::default();
+            match oauth2client::from_refresh_token(&conf.client(), rtoken, &key) {
+                Ok(tokens) => {
+                    debug!("Token retrieved usgin refresh token: {:?}", tokens);
+                    Some(tokens)
+                }
+                Err(BearerError::OAuth2Error(ref err)) if err.error == "invalid_grant" => {
+                    println!("The refresh token has been rejected ({}).", err);
+                    None
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        _ => None,
+    };
+
+    let tokens = match tokens {
+        Some(tokens) => tokens,
+        None if manual => {
+            let tokens = oauth2::get_tokens_manually(&conf, 6750, login)?;
+            debug!("Token retrieved using auth code: {:?}", tokens);
             tokens
         }
         None => {
@@ -23,13 +49,14 @@ pub fn command(config_dir: &str, client_name: &str) -> BearerResult<()> {
             println!("Visit to finish the configuration: http://localhost:6750/callback");
 
             debug!("Start server to retrieve tokens");
-            let tokens = oauth2::get_tokens(&conf, 6750)?;
+            let tokens = oauth2::get_tokens(&conf, 6750, login)?;
             debug!("Token retrieved using auth code: {:?}", tokens);
             tokens
         }
     };
 
     conf.set_tokens(tokens);
+    conf.clear_scoped_tokens();
     conf.write()?;
     println!("Tokens retrieved succesfully");
     Ok(())
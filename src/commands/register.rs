@@ -1,48 +1,62 @@
-use std::io;
-use std::io::prelude::*;
+use std::collections::HashMap;
 
-use super::super::config::Config;
-use super::super::helpers::path::build_path;
-use super::super::helpers::oauth2;
-use super::super::helpers::providers;
-use super::super::results::{BearerResult, BearerError};
+use bearer::config;
+use bearer::config::{Config, TokenExchange};
+use bearer::helpers::path::build_new_path;
+use bearer::helpers::client_auth::AuthMethod;
+use super::oauth2;
+use super::oauth2::LoginOptions;
+use super::prompt::read_stdin;
+use bearer::helpers::providers;
+use bearer::results::{BearerResult, BearerError};
 
-fn read_stdin(message: &str) -> BearerResult<String> {
-    print!("{}", message);
-    io::stdout().flush().unwrap();
-
-    let mut buffer = String::new();
-    match io::stdin().read_line(&mut buffer) {
-        Ok(_) => Ok(buffer.trim().to_string()),
-        Err(err) => Err(BearerError::IOError(format!("{}", err))),
-    }
-}
 
-
-pub fn command(config_dir: &str, client_name: &str) -> BearerResult<()> {
+pub fn command(config_dir: &str,
+               client_name: &str,
+               manual: bool,
+               login: LoginOptions)
+               -> BearerResult<()> {
 
     debug!("Registering new client {} in directory {}",
            client_name,
            config_dir);
-    let (_, exists) = build_path(config_dir, client_name)?;
-    if exists {
-        return Err(BearerError::ValueError(format!("Client {} already registered", client_name)));
-    }
+    build_new_path(config_dir, client_name)?;
     println!("Before continue, register the a client with the following url to the OAuth2 \
               Provider:");
     println!("");
     println!("http://localhost:6750/callback");
     println!("");
-    println!("Ensure your port is not already open by another service.");
-    println!("If the provider require a https url, please run an https reverse proxy before \
-              continue.");
+    if !manual {
+        println!("Ensure your port is not already open by another service.");
+        println!("If the provider require a https url, please run an https reverse proxy \
+                  before continue.");
+    }
     println!("");
     let mut provider_name = read_stdin("Enter the OAuth2.0 Provider Name: ")?;
 
-    let (authorize_url, token_url) = match providers::get_provider(&provider_name) {
+    let provider = match providers::get_provider(config_dir, &provider_name)? {
         Some(provider) => {
+            let mut values = HashMap::new();
+            for placeholder in provider.placeholders() {
+                let value = read_stdin(format!("Enter the {} of the {} provider: ",
+                                               placeholder,
+                                               provider.name)
+                    .as_str())?;
+                if value.is_empty() {
+                    return Err(BearerError::ValueError(format!("The {} is required",
+                                                               placeholder)));
+                }
+                values.insert(placeholder, value);
+            }
+            Some(provider.with_values(&values)?)
+        }
+        None => None,
+    };
+    let (authorize_url, token_url) = match provider {
+        Some(ref provider) => {
             provider_name = provider.name.to_string();
-            (provider.authorize_url.to_string(), provider.token_url.to_string())
+            let settings = config::http_settings(config_dir)?;
+            provider.endpoints(&settings)?
         }
         None => {
             let authorize_url = read_stdin("Enter the OAuth2.0 Authorize Url: ")?;
@@ -52,7 +66,61 @@ pub fn command(config_dir: &str, client_name: &str) -> BearerResult<()> {
     };
     let client_id = read_stdin("Enter the Client Id: ")?;
     let secret = read_stdin("Enter the Client Secret: ")?;
-    let scope = read_stdin("Enter the scope (optional): ")?;
+    let default_scope = provider.as_ref().and_then(|provider| provider.scope.clone());
+    let scope = match default_scope {
+        Some(ref default_scope) => {
+            let scope = read_stdin(format!("Enter the scope (default {}): ", default_scope)
+                .as_str())?;
+            if scope.is_empty() {
+                default_scope.to_string()
+            } else {
+                scope
+            }
+        }
+        None => read_stdin("Enter the scope (optional): ")?,
+    };
+    let subject = read_stdin("Exchange the token of another client (optional, client name, \
+                              or - to read it from stdin): ")?;
+    let token_exchange = match subject.len() {
+        0 => None,
+        _ => {
+            let audience = read_stdin("Enter the audience of the exchanged token (optional): ")?;
+            let mut exchange = TokenExchange::default();
+            if subject == "-" {
+                exchange.subject_token_file = Some(subject);
+            } else {
+                exchange.subject_client = Some(subject);
+            }
+            if audience.len() > 0 {
+                exchange.audience = Some(audience);
+            }
+            Some(exchange)
+        }
+    };
+    let default_method = provider.as_ref()
+        .and_then(|provider| provider.token_endpoint_auth_method.as_ref())
+        .map(|method| method.as_str());
+    let default_method = AuthMethod::from_name(default_method)?;
+    let auth_method = read_stdin(format!("Enter the token endpoint auth method (optional, \
+                                          default {}): ",
+                                         default_method.name())
+        .as_str())?;
+    let auth_method = match auth_method.len() {
+        0 => default_method,
+        _ => AuthMethod::from_name(Some(auth_method.as_str()))?,
+    };
+    let (private_key_file, private_key_alg) = match auth_method {
+        AuthMethod::PrivateKeyJwt => {
+            let path = read_stdin("Enter the private key file (PEM or DER, PKCS#8): ")?;
+            let alg = read_stdin("Enter the signing algorithm (optional, RS256 or ES256): ")?;
+            (Some(path),
+             match alg.len() {
+                0 => None,
+                _ => Some(alg),
+            })
+        }
+        _ => (None, None),
+    };
 
     let mut conf = Config::new(config_dir,
                                client_name,
@@ -65,12 +133,32 @@ pub fn command(config_dir: &str, client_name: &str) -> BearerResult<()> {
                                    0 => None,
                                    _ => Some(scope.as_str()),
                                })?;
+    if auth_method != AuthMethod::ClientSecretPost {
+        conf.set_auth_method(Some(auth_method.name()),
+                             private_key_file.as_ref().map(|path| path.as_str()),
+                             private_key_alg.as_ref().map(|alg| alg.as_str()));
+    }
+    if let Some(provider) = provider {
+        conf.set_revocation_url(provider.revocation_url);
+        conf.set_extra_params(provider.authorize_params, provider.token_params);
+    }
 
-    println!("");
-    println!("Visit to finish the configuration: http://localhost:6750/callback");
+    if token_exchange.is_some() {
+        conf.set_token_exchange(token_exchange);
+        conf.write()?;
+        println!("Client registered, its tokens will be obtained by token exchange");
+        return Ok(());
+    }
 
-    debug!("Start server to retrieve tokens");
-    let tokens = oauth2::get_tokens(&conf, 6750)?;
+    let tokens = if manual {
+        oauth2::get_tokens_manually(&conf, 6750, login)?
+    } else {
+        println!("");
+        println!("Visit to finish the configuration: http://localhost:6750/callback");
+
+        debug!("Start server to retrieve tokens");
+        oauth2::get_tokens(&conf, 6750, login)?
+    };
     debug!("Token retrieved: {:?}", tokens);
     conf.set_tokens(tokens);
     conf.write()?;
@@ -0,0 +1,64 @@
+use std::fs;
+
+use bearer::config::Config;
+use bearer::helpers::oauth2client::revoke_token;
+use bearer::helpers::path::{build_path, build_state_path, find_system_client,
+                                  SYSTEM_CONFIG_DIR};
+use bearer::results::{BearerResult, BearerError};
+
+
+/// Revoke the tokens of the client and of its profiles.
+fn revoke(config_dir: &str, client_name: &str) -> BearerResult<()> {
+    let conf = Config::from_file(config_dir, client_name)?;
+    let mut profiles = vec![None];
+    profiles.extend(conf.profiles().iter().map(|profile| Some(profile.to_string())));
+    for profile in profiles {
+        let conf = Config::from_file_with_profile(config_dir,
+                                                  client_name,
+                                                  profile.as_ref().map(|name| name.as_str()))?;
+        if conf.access_token().is_none() {
+            continue;
+        }
+        if conf.client().revocation_url.is_none() {
+            return Err(BearerError::ValueError(format!("Client {} has no revocation_url, \
+                                                        cannot revoke its tokens",
+                                                       client_name)));
+        }
+        // Revoking the refresh token may revoke the access tokens too, see RFC 7009.
+        if let Some(refresh_token) = conf.refresh_token() {
+            revoke_token(&conf.client(), refresh_token, "refresh_token")?;
+        }
+        if let (Some(access_token), Some(false)) = (conf.access_token(), conf.expired()) {
+            revoke_token(&conf.client(), access_token, "access_token")?;
+        }
+    }
+    Ok(())
+}
+
+
+pub fn command(config_dir: &str, client_name: &str, revoke_tokens: bool) -> BearerResult<()> {
+    debug!("Removing client {} from directory {}", client_name, config_dir);
+    let (path, exists) = build_path(config_dir, client_name)?;
+    if !exists {
+        if let Some(path) = find_system_client(SYSTEM_CONFIG_DIR, client_name)? {
+            return Err(BearerError::ValueError(format!("Client {} is defined in {} and cannot \
+                                                        be removed",
+                                                       client_name,
+                                                       path)));
+        }
+        return Err(BearerError::ValueError(format!("Client {} not registered", client_name)));
+    }
+    if revoke_tokens {
+        revoke(config_dir, client_name)?;
+    }
+    if let Err(err) = fs::remove_file(path.as_str()) {
+        return Err(BearerError::IOError(format!("Cannot remove file {}: {}", path, err)));
+    }
+    if let (state_path, true) = build_state_path(config_dir, client_name)? {
+        if let Err(err) = fs::remove_file(state_path.as_str()) {
+            return Err(BearerError::IOError(format!("Cannot remove file {}: {}", state_path, err)));
+        }
+    }
+    println!("Client {} removed", client_name);
+    Ok(())
+}
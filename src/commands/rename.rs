@@ -0,0 +1,39 @@
+use std::fs;
+
+use bearer::helpers::path::{build_path, build_new_path, build_state_path, find_system_client,
+                                  SYSTEM_CONFIG_DIR};
+use bearer::results::{BearerResult, BearerError};
+
+
+fn rename(path: &str, new_path: &str) -> BearerResult<()> {
+    if let Err(err) = fs::rename(path, new_path) {
+        return Err(BearerError::IOError(format!("Cannot rename file {} to {}: {}",
+                                                path,
+                                                new_path,
+                                                err)));
+    }
+    Ok(())
+}
+
+
+pub fn command(config_dir: &str, client_name: &str, new_name: &str) -> BearerResult<()> {
+    debug!("Renaming client {} to {} in directory {}", client_name, new_name, config_dir);
+    let (path, exists) = build_path(config_dir, client_name)?;
+    if !exists {
+        if let Some(path) = find_system_client(SYSTEM_CONFIG_DIR, client_name)? {
+            return Err(BearerError::ValueError(format!("Client {} is defined in {} and cannot \
+                                                        be renamed",
+                                                       client_name,
+                                                       path)));
+        }
+        return Err(BearerError::ValueError(format!("Client {} not registered", client_name)));
+    }
+    let new_path = build_new_path(config_dir, new_name)?;
+    rename(path.as_str(), new_path.as_str())?;
+    if let (state_path, true) = build_state_path(config_dir, client_name)? {
+        let (new_state_path, _) = build_state_path(config_dir, new_name)?;
+        rename(state_path.as_str(), new_state_path.as_str())?;
+    }
+    println!("Client {} renamed to {}", client_name, new_name);
+    Ok(())
+}
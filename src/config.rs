@@ -1,20 +1,41 @@
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs;
 use std::fs::File;
 use std::os::unix::fs::OpenOptionsExt;
 use std::fs::OpenOptions;
 use std::io::prelude::*;
+use std::path::Path;
 
+use libc;
+use serde::Serialize;
 use toml;
 use toml::value::Datetime;
+use url::Url;
 use chrono::Duration;
 use chrono::datetime::DateTime;
 use chrono::offset::utc::UTC;
-use super::helpers::path::build_path;
+use super::helpers::http::HttpSettings;
+use super::helpers::path::{build_path, build_new_path, build_file_path, build_state_path, build_state_file_path,
+                           find_system_client, state_dir, SETTINGS_FILE,
+                           SYSTEM_CONFIG_DIR};
 use super::results::{BearerResult, BearerError};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Version of the format of the client files.
+///
+/// Version 1 is the format of bearer 0.1, with a `server_url`, version 2 stores
+/// the client in a `[client]` section.
+pub const SCHEMA_VERSION: u32 = 2;
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct TomlConfig {
+    /// Not set by the versions of bearer prior to the schema versioning.
+    pub version: Option<u32>,
     pub client: Client,
     pub tokens: Option<Tokens>,
+    pub scoped_tokens: Option<Vec<ScopedTokens>>,
+    pub profiles: Option<BTreeMap<String, Profile>>,
 }
 
 
@@ -26,9 +47,339 @@ struct Client {
     pub client_id: String,
     pub secret: String,
     pub scope: Option<String>,
+    pub token_endpoint_auth_method: Option<String>,
+    pub private_key_file: Option<String>,
+    pub private_key_alg: Option<String>,
+    pub default_expires_in: Option<usize>,
+    pub proxy: Option<String>,
+    pub ca_bundle: Option<String>,
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    pub insecure_skip_verify: Option<bool>,
+    pub connect_timeout: Option<u64>,
+    pub read_timeout: Option<u64>,
+    pub max_retries: Option<u32>,
+    /// Token revocation endpoint, see RFC 7009.
+    pub revocation_url: Option<String>,
+    /// Token introspection endpoint, see RFC 7662.
+    pub introspection_url: Option<String>,
+    /// Resource indicators of the tokens, see RFC 8707.
+    pub resources: Option<Vec<String>>,
+    pub authorize_params: Option<BTreeMap<String, String>>,
+    pub token_params: Option<BTreeMap<String, String>>,
+    pub token_exchange: Option<TokenExchange>,
+}
+
+
+impl Client {
+    /// The client with the settings of the profile applied.
+    fn with_profile(&self, profile: &Profile) -> Client {
+        Client {
+            provider: profile.provider.clone().unwrap_or_else(|| self.provider.clone()),
+            token_url: profile.token_url.clone().unwrap_or_else(|| self.token_url.clone()),
+            authorize_url: profile.authorize_url
+                .clone()
+                .unwrap_or_else(|| self.authorize_url.clone()),
+            client_id: profile.client_id.clone().unwrap_or_else(|| self.client_id.clone()),
+            secret: profile.secret.clone().unwrap_or_else(|| self.secret.clone()),
+            scope: profile.scope.clone().or_else(|| self.scope.clone()),
+            token_endpoint_auth_method: profile.token_endpoint_auth_method
+                .clone()
+                .or_else(|| self.token_endpoint_auth_method.clone()),
+            private_key_file: profile.private_key_file
+                .clone()
+                .or_else(|| self.private_key_file.clone()),
+            private_key_alg: profile.private_key_alg
+                .clone()
+                .or_else(|| self.private_key_alg.clone()),
+            default_expires_in: profile.default_expires_in.or(self.default_expires_in),
+            proxy: profile.proxy.clone().or_else(|| self.proxy.clone()),
+            ca_bundle: profile.ca_bundle.clone().or_else(|| self.ca_bundle.clone()),
+            client_cert: profile.client_cert.clone().or_else(|| self.client_cert.clone()),
+            client_key: profile.client_key.clone().or_else(|| self.client_key.clone()),
+            insecure_skip_verify: profile.insecure_skip_verify.or(self.insecure_skip_verify),
+            connect_timeout: profile.connect_timeout.or(self.connect_timeout),
+            read_timeout: profile.read_timeout.or(self.read_timeout),
+            max_retries: profile.max_retries.or(self.max_retries),
+            revocation_url: profile.revocation_url
+                .clone()
+                .or_else(|| self.revocation_url.clone()),
+            introspection_url: profile.introspection_url
+                .clone()
+                .or_else(|| self.introspection_url.clone()),
+            resources: profile.resources.clone().or_else(|| self.resources.clone()),
+            authorize_params: profile.authorize_params
+                .clone()
+                .or_else(|| self.authorize_params.clone()),
+            token_params: profile.token_params.clone().or_else(|| self.token_params.clone()),
+            token_exchange: profile.token_exchange
+                .clone()
+                .or_else(|| self.token_exchange.clone()),
+        }
+    }
+}
+
+
+/// Client file of bearer 0.1.
+#[derive(Debug, Deserialize)]
+struct LegacyConfig {
+    pub server_url: String,
+    pub client_id: String,
+    pub secret: String,
+    pub scope: Option<String>,
+    pub tokens: Option<Tokens>,
+}
+
+impl LegacyConfig {
+    fn migrate(self) -> TomlConfig {
+        let server_url = self.server_url.trim_right_matches('/');
+        let provider = match Url::parse(server_url) {
+            Ok(ref url) => url.host_str().unwrap_or(server_url).to_string(),
+            Err(_) => server_url.to_string(),
+        };
+        TomlConfig {
+            version: Some(SCHEMA_VERSION),
+            client: Client {
+                provider,
+                token_url: format!("{}/token", server_url),
+                authorize_url: format!("{}/authorize", server_url),
+                client_id: self.client_id,
+                secret: self.secret,
+                scope: self.scope,
+                token_endpoint_auth_method: None,
+                private_key_file: None,
+                private_key_alg: None,
+                default_expires_in: None,
+                proxy: None,
+                ca_bundle: None,
+                client_cert: None,
+                client_key: None,
+                insecure_skip_verify: None,
+                connect_timeout: None,
+                read_timeout: None,
+                max_retries: None,
+                revocation_url: None,
+                introspection_url: None,
+                resources: None,
+                authorize_params: None,
+                token_params: None,
+                token_exchange: None,
+            },
+            tokens: self.tokens,
+            scoped_tokens: None,
+            profiles: None,
+        }
+    }
+}
+
+
+/// Schema version of a client file.
+fn schema_version(value: &toml::Value) -> BearerResult<u32> {
+    let table = match value.as_table() {
+        Some(table) => table,
+        None => return Err(BearerError::ParseError("Not a table".to_string())),
+    };
+    match table.get("version") {
+        Some(version) => {
+            match version.as_integer() {
+                Some(version) if version > 0 => Ok(version as u32),
+                _ => Err(BearerError::ParseError(format!("Invalid version {:?}", version))),
+            }
+        }
+        None if table.contains_key("server_url") => Ok(1),
+        None => Ok(SCHEMA_VERSION),
+    }
+}
+
+
+/// Parse a client file, migrated to the current schema version.
+///
+/// Return the config and the schema version of the file.
+fn parse_config(path: &str, buf: &[u8]) -> BearerResult<(TomlConfig, u32)> {
+    let parse_error = |err: toml::de::Error| {
+        BearerError::ParseError(format!("Cannot parse config file {}: {:?}", path, err))
+    };
+    let value: toml::Value = toml::from_slice(buf).map_err(&parse_error)?;
+    let version = match schema_version(&value) {
+        Ok(version) => version,
+        Err(err) => {
+            return Err(BearerError::ParseError(format!("Cannot parse config file {}: {}",
+                                                       path,
+                                                       err)))
+        }
+    };
+    let mut config = match version {
+        1 => toml::from_slice::<LegacyConfig>(buf).map_err(&parse_error)?.migrate(),
+        SCHEMA_VERSION => toml::from_slice::<TomlConfig>(buf).map_err(&parse_error)?,
+        _ => {
+            return Err(BearerError::ValueError(format!("Config file {} has the schema version \
+                                                        {}, upgrade bearer to read it",
+                                                       path,
+                                                       version)))
+        }
+    };
+    config.version = Some(SCHEMA_VERSION);
+    Ok((config, version))
+}
+
+
+/// Overrides of the `[client]` for an environment, with its own tokens.
+#[derive(Debug, Default, Serialize, Clone, Deserialize)]
+struct Profile {
+    pub provider: Option<String>,
+    pub token_url: Option<String>,
+    pub authorize_url: Option<String>,
+    pub client_id: Option<String>,
+    pub secret: Option<String>,
+    pub scope: Option<String>,
+    pub token_endpoint_auth_method: Option<String>,
+    pub private_key_file: Option<String>,
+    pub private_key_alg: Option<String>,
+    pub default_expires_in: Option<usize>,
+    pub proxy: Option<String>,
+    pub ca_bundle: Option<String>,
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    pub insecure_skip_verify: Option<bool>,
+    pub connect_timeout: Option<u64>,
+    pub read_timeout: Option<u64>,
+    pub max_retries: Option<u32>,
+    pub revocation_url: Option<String>,
+    pub introspection_url: Option<String>,
+    /// Resource indicators of the tokens, see RFC 8707.
+    pub resources: Option<Vec<String>>,
+    pub authorize_params: Option<BTreeMap<String, String>>,
+    pub token_params: Option<BTreeMap<String, String>>,
+    pub token_exchange: Option<TokenExchange>,
+    pub tokens: Option<Tokens>,
+    pub scoped_tokens: Option<Vec<ScopedTokens>>,
+}
+
+
+impl Profile {
+    /// Whether the profile sets its own value of a setting of the client.
+    fn overrides(&self, field: &str) -> bool {
+        match field {
+            "authorize_url" => self.authorize_url.is_some(),
+            "token_url" => self.token_url.is_some(),
+            "client_id" => self.client_id.is_some(),
+            "scope" => self.scope.is_some(),
+            _ => false,
+        }
+    }
+}
+
+
+impl TomlConfig {
+    fn has_tokens(&self) -> bool {
+        self.tokens.is_some() || self.scoped_tokens.is_some() ||
+        self.profiles.as_ref().is_some_and(|profiles| {
+            profiles.values().any(|profile| {
+                profile.tokens.is_some() || profile.scoped_tokens.is_some()
+            })
+        })
+    }
+
+    /// The client definition, without the tokens of the client and of its profiles.
+    fn without_tokens(&self) -> TomlConfig {
+        let mut config = self.clone();
+        config.tokens = None;
+        config.scoped_tokens = None;
+        if let Some(ref mut profiles) = config.profiles {
+            for profile in profiles.values_mut() {
+                profile.tokens = None;
+                profile.scoped_tokens = None;
+            }
+        }
+        config
+    }
 }
 
 
+/// Tokens of a client kept in the token store, see `helpers::path::state_dir`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TomlState {
+    pub tokens: Option<Tokens>,
+    pub scoped_tokens: Option<Vec<ScopedTokens>>,
+    pub profiles: Option<BTreeMap<String, ProfileState>>,
+}
+
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ProfileState {
+    pub tokens: Option<Tokens>,
+    pub scoped_tokens: Option<Vec<ScopedTokens>>,
+}
+
+
+impl TomlState {
+    fn from_config(config: &TomlConfig) -> Self {
+        TomlState {
+            tokens: config.tokens.clone(),
+            scoped_tokens: config.scoped_tokens.clone(),
+            profiles: config.profiles.as_ref().map(|profiles| {
+                profiles.iter()
+                    .map(|(name, profile)| {
+                        (name.to_string(),
+                         ProfileState {
+                             tokens: profile.tokens.clone(),
+                             scoped_tokens: profile.scoped_tokens.clone(),
+                         })
+                    })
+                    .collect()
+            }),
+        }
+    }
+
+    /// Replace the tokens of the config, the profiles must still be defined.
+    fn apply(self, config: &mut TomlConfig) {
+        config.tokens = self.tokens;
+        config.scoped_tokens = self.scoped_tokens;
+        if let (Some(states), Some(profiles)) = (self.profiles, config.profiles.as_mut()) {
+            for (name, state) in states {
+                if let Some(profile) = profiles.get_mut(&name) {
+                    profile.tokens = state.tokens;
+                    profile.scoped_tokens = state.scoped_tokens;
+                }
+            }
+        }
+    }
+}
+
+
+/// Obtain the tokens of a client with an OAuth 2.0 Token Exchange, see RFC 8693.
+#[derive(Debug, Default, Serialize, Clone, Deserialize)]
+pub struct TokenExchange {
+    /// Registered client providing the subject token.
+    pub subject_client: Option<String>,
+    /// File containing the subject token, `-` reads it from stdin.
+    pub subject_token_file: Option<String>,
+    pub subject_token_type: Option<String>,
+    /// Registered client providing the actor token, if any.
+    pub actor_client: Option<String>,
+    pub actor_token_type: Option<String>,
+    pub audience: Option<String>,
+    pub requested_token_type: Option<String>,
+}
+
+
+/// Global settings of the config dir, see `helpers::path::SETTINGS_FILE`.
+#[derive(Debug, Default, Deserialize)]
+struct Settings {
+    pub tls: Option<TlsSettings>,
+}
+
+
+#[derive(Debug, Default, Clone, Deserialize)]
+struct TlsSettings {
+    pub ca_bundle: Option<String>,
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    pub insecure_skip_verify: Option<bool>,
+}
+
+
+#[derive(Default)]
 pub struct ClientRef<'a> {
     pub provider: &'a str,
     pub token_url: &'a str,
@@ -36,6 +387,24 @@ pub struct ClientRef<'a> {
     pub client_id: &'a str,
     pub secret: &'a str,
     pub scope: Option<&'a str>,
+    pub token_endpoint_auth_method: Option<&'a str>,
+    pub private_key_file: Option<&'a str>,
+    pub private_key_alg: Option<&'a str>,
+    pub default_expires_in: Option<usize>,
+    pub proxy: Option<&'a str>,
+    pub ca_bundle: Option<&'a str>,
+    pub client_cert: Option<&'a str>,
+    pub client_key: Option<&'a str>,
+    pub insecure_skip_verify: bool,
+    pub connect_timeout: Option<u64>,
+    pub read_timeout: Option<u64>,
+    pub max_retries: Option<u32>,
+    pub revocation_url: Option<&'a str>,
+    pub introspection_url: Option<&'a str>,
+    pub resources: Option<&'a [String]>,
+    pub authorize_params: Option<&'a BTreeMap<String, String>>,
+    pub token_params: Option<&'a BTreeMap<String, String>>,
+    pub token_exchange: Option<&'a TokenExchange>,
 }
 
 
@@ -50,7 +419,13 @@ impl Tokens {
     pub fn new(access_token: &str, expires_in: usize, refresh_token: Option<&str>) -> Self {
         let now: DateTime<UTC> = UTC::now();
         let duration = Duration::seconds(expires_in as i64);
-        let expires_at = now + duration;
+        Tokens::expiring_at(access_token, now + duration, refresh_token)
+    }
+
+    pub fn expiring_at(access_token: &str,
+                       expires_at: DateTime<UTC>,
+                       refresh_token: Option<&str>)
+                       -> Self {
         let expires_at = expires_at.to_rfc3339().as_str().parse::<Datetime>().unwrap();
         Tokens {
             access_token: access_token.to_string(),
@@ -61,6 +436,84 @@ impl Tokens {
             },
         }
     }
+
+    pub fn expires_at(&self) -> DateTime<UTC> {
+        let expire_string = self.expires_at.to_string();
+        expire_string.parse::<DateTime<UTC>>().unwrap()
+    }
+
+    pub fn expired(&self) -> bool {
+        let now: DateTime<UTC> = UTC::now();
+        let date = self.expires_at();
+        debug!("{:?} > {:?}: {}", now, date, now > date);
+        now > date
+    }
+}
+
+
+/// Identify the tokens requested with a narrowed scope, a given audience or resource.
+///
+/// The default key designates the `[tokens]` of the client.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TokenKey<'a> {
+    pub scope: Option<&'a str>,
+    pub audience: Option<&'a str>,
+    pub resource: Option<&'a str>,
+}
+
+impl<'a> TokenKey<'a> {
+    pub fn is_default(&self) -> bool {
+        self.scope.is_none() && self.audience.is_none() && self.resource.is_none()
+    }
+}
+
+
+/// Tokens obtained for a `TokenKey` that is not the default one.
+///
+/// They never hold a refresh token, the one of the `[tokens]` is used.
+#[derive(Debug, Serialize, Clone, Deserialize)]
+struct ScopedTokens {
+    pub scope: Option<String>,
+    pub audience: Option<String>,
+    pub resource: Option<String>,
+    pub tokens: Tokens,
+}
+
+impl ScopedTokens {
+    fn matches(&self, key: &TokenKey) -> bool {
+        self.scope.as_ref().map(|scope| scope.as_str()) == key.scope &&
+        self.audience.as_ref().map(|audience| audience.as_str()) == key.audience &&
+        self.resource.as_ref().map(|resource| resource.as_str()) == key.resource
+    }
+}
+
+
+/// Changes of the `[client]` section of a registered client, see `Config::edit`.
+#[derive(Debug, Default, Clone)]
+pub struct ClientEdit {
+    pub authorize_url: Option<String>,
+    pub token_url: Option<String>,
+    pub revocation_url: Option<String>,
+    pub client_id: Option<String>,
+    pub secret: Option<String>,
+    /// An empty scope removes it.
+    pub scope: Option<String>,
+}
+
+impl ClientEdit {
+    pub fn is_empty(&self) -> bool {
+        self.authorize_url.is_none() && self.token_url.is_none() &&
+        self.revocation_url.is_none() && self.client_id.is_none() &&
+        self.secret.is_none() && self.scope.is_none()
+    }
+}
+
+
+fn validate_url(name: &str, url: &str) -> BearerResult<()> {
+    match Url::parse(url) {
+        Ok(ref url) if url.scheme() == "http" || url.scheme() == "https" => Ok(()),
+        _ => Err(BearerError::ValueError(format!("Invalid {} {}", name, url))),
+    }
 }
 
 
@@ -70,46 +523,215 @@ pub struct Config {
     client_name: String,
     path: String,
     config: TomlConfig,
+    settings: TlsSettings,
+    profile: Option<String>,
+    client: Client,
+    migrated_from: Option<u32>,
+    /// File of the token store.
+    state_path: String,
+    /// Whether the client file must be written, not only the token store.
+    client_changed: bool,
+    read_only: bool,
+}
+
+
+fn read_file(path: &str) -> BearerResult<Vec<u8>> {
+    let file = File::open(path);
+    if file.is_err() {
+        return Err(BearerError::IOError(format!("Cannot open file {:?}: {:?}",
+                                                path,
+                                                file.err().unwrap())));
+    }
+    let mut file = file.unwrap();
+
+    let mut buf: Vec<u8> = Vec::new();
+    if let Err(err) = file.read_to_end(&mut buf) {
+        return Err(BearerError::IOError(format!("Cannot read file {:?}: {:?}", path, err)));
+    }
+    Ok(buf)
+}
+
+
+/// Whether the current user cannot write the file, whatever its permission bits.
+fn is_read_only(path: &str) -> bool {
+    match CString::new(path) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), libc::W_OK) != 0 },
+        Err(_) => true,
+    }
+}
+
+
+fn write_file<T: Serialize>(path: &str, content: &T, mode: u32) -> BearerResult<()> {
+    let filecontent = toml::to_string(content);
+    if let Err(err) = filecontent {
+        return Err(BearerError::SerializationError(format!("Cannot serialize file {}: {:?}",
+                                                           path,
+                                                           err)));
+    }
+    let filecontent = filecontent.unwrap();
+    let file = OpenOptions::new()
+        .mode(mode)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path);
+
+    if let Err(err) = file {
+        return Err(BearerError::IOError(format!("Cannot open file {}: {:?}", path, err)));
+    }
+    let mut file = file.unwrap();
+    let written = file.write_all(filecontent.as_bytes());
+    if let Err(err) = written {
+        return Err(BearerError::IOError(format!("IOError while writing file {}: {}", path, err)));
+    }
+    Ok(())
+}
+
+
+fn load_state(path: &str) -> BearerResult<TomlState> {
+    let buf = read_file(path)?;
+    let state: Result<TomlState, toml::de::Error> = toml::from_slice(buf.as_slice());
+    match state {
+        Ok(state) => Ok(state),
+        Err(err) => {
+            Err(BearerError::ParseError(format!("Cannot parse token file {}: {:?}", path, err)))
+        }
+    }
+}
+
+
+fn load_settings(config_dir: &str) -> BearerResult<TlsSettings> {
+    let (path, exists) = build_file_path(config_dir, SETTINGS_FILE)?;
+    if !exists {
+        return Ok(TlsSettings::default());
+    }
+    let buf = read_file(path.as_str())?;
+    let settings: Result<Settings, toml::de::Error> = toml::from_slice(buf.as_slice());
+    match settings {
+        Ok(settings) => Ok(settings.tls.unwrap_or_default()),
+        Err(err) => {
+            Err(BearerError::ParseError(format!("Cannot parse settings file {}: {:?}", path, err)))
+        }
+    }
+}
+
+
+/// Connection settings of the config dir, for requests made without a client.
+pub fn http_settings(config_dir: &str) -> BearerResult<HttpSettings> {
+    let settings = load_settings(config_dir)?;
+    Ok(HttpSettings {
+        ca_bundle: settings.ca_bundle,
+        client_cert: settings.client_cert,
+        client_key: settings.client_key,
+        insecure_skip_verify: settings.insecure_skip_verify.unwrap_or(false),
+        ..HttpSettings::default()
+    })
 }
 
 
 impl Config {
     pub fn from_file(config_dir: &str, client_name: &str) -> BearerResult<Self> {
+        let state_dir = state_dir(config_dir)?;
+        Config::from_dirs(config_dir, SYSTEM_CONFIG_DIR, state_dir.as_str(), client_name)
+    }
+
+    /// Load a client of the config dir, or a read-only client of the system dir,
+    /// with its tokens from the state dir.
+    fn from_dirs(config_dir: &str,
+                 system_dir: &str,
+                 state_dir: &str,
+                 client_name: &str)
+                 -> BearerResult<Self> {
 
         let (path, exists) = build_path(config_dir, client_name)?;
-        if !exists {
-            return Err(BearerError::ValueError(format!("Client {} not registered", client_name)));
-        }
+        let (path, system) = if exists {
+            (path, false)
+        } else {
+            match find_system_client(system_dir, client_name)? {
+                Some(path) => (path, true),
+                None => {
+                    return Err(BearerError::ValueError(format!("Client {} not registered",
+                                                               client_name)))
+                }
+            }
+        };
 
-        let file = File::open(path.as_str());
-        if file.is_err() {
-            return Err(BearerError::IOError(format!("Cannot open file {:?}: {:?}",
-                                                    path,
-                                                    file.err().unwrap())));
+        let buf = read_file(path.as_str())?;
+        let settings = load_settings(config_dir)?;
+
+        let (mut cf, version) = parse_config(path.as_str(), buf.as_slice())?;
+        let read_only = system || is_read_only(path.as_str());
+        let (state_path, state_exists) = build_state_file_path(state_dir, client_name)?;
+        // Tokens found in a writable client file are moved to the token store, and an
+        // older schema is rewritten, both on the next write only.
+        let migrated_from = if version < SCHEMA_VERSION { Some(version) } else { None };
+        let client_changed = !read_only && (cf.has_tokens() || migrated_from.is_some());
+        if state_exists {
+            load_state(state_path.as_str())?.apply(&mut cf);
         }
-        let mut file = file.unwrap();
+        let conf = Config {
+            config_dir: config_dir.to_string(),
+            client_name: client_name.to_string(),
+            path: path.to_owned(),
+            client: cf.client.clone(),
+            config: cf,
+            settings,
+            profile: None,
+            migrated_from,
+            state_path,
+            client_changed,
+            read_only,
+        };
+        Ok(conf)
+    }
 
-        let mut buf: Vec<u8> = Vec::new();
-        if let Err(err) = file.read_to_end(&mut buf) {
-            return Err(BearerError::IOError(format!("Cannot read file {:?}: {:?}", path, err)));
+    /// Whether the client definition cannot be modified, such as the clients
+    /// of the system dir.
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Keep the file of an older schema version before it is rewritten.
+    fn backup(&self, version: u32) -> BearerResult<()> {
+        let backup = format!("{}.v{}.bak", self.path, version);
+        if Path::new(backup.as_str()).exists() {
+            return Ok(());
+        }
+        info!("Migrating {} from schema version {}, backup in {}",
+              self.path,
+              version,
+              backup);
+        if let Err(err) = fs::copy(self.path.as_str(), backup.as_str()) {
+            return Err(BearerError::IOError(format!("Cannot backup file {} to {}: {}",
+                                                    self.path,
+                                                    backup,
+                                                    err)));
         }
+        Ok(())
+    }
 
-        let conf: Result<TomlConfig, toml::de::Error> = toml::from_slice(buf.as_slice());
-        match conf {
-            Ok(cf) => {
-                Ok(Config {
-                    config_dir: config_dir.to_string(),
-                    client_name: client_name.to_string(),
-                    path: path.to_owned(),
-                    config: cf,
-                })
-            }
-            Err(err) => {
-                Err(BearerError::ParseError(format!("Cannot parse config file {}: {:?}",
-                                                    path,
-                                                    err)))
-            }
+    /// Whether the client file must be rewritten, to move its tokens to the token
+    /// store or to migrate it, which is only done by `write`.
+    pub fn has_changes(&self) -> bool {
+        self.client_changed
+    }
+
+    /// The schema version of the file if it has been migrated while loading, the
+    /// file is only rewritten by `write`.
+    pub fn migrated_from(&self) -> Option<u32> {
+        self.migrated_from
+    }
+
+    /// Load a client, with the settings and the tokens of the given profile.
+    pub fn from_file_with_profile(config_dir: &str,
+                                  client_name: &str,
+                                  profile: Option<&str>)
+                                  -> BearerResult<Self> {
+        let mut conf = Config::from_file(config_dir, client_name)?;
+        if let Some(profile) = profile {
+            conf.select_profile(profile)?;
         }
+        Ok(conf)
     }
 
     pub fn new(config_dir: &str,
@@ -122,116 +744,421 @@ impl Config {
                scope: Option<&str>)
                -> BearerResult<Self> {
 
-        let (path, exists) = build_path(config_dir, client_name)?;
-        if exists {
-            return Err(BearerError::ValueError(format!("Client {} already registered",
-                                                       client_name)));
-        }
+        let path = build_new_path(config_dir, client_name)?;
+        let settings = load_settings(config_dir)?;
 
         let config = TomlConfig {
+            version: Some(SCHEMA_VERSION),
             client: Client {
                 provider: provider.to_string(),
                 authorize_url: authorize_url.to_string(),
                 token_url: token_url.to_string(),
                 client_id: client_id.to_string(),
                 secret: secret.to_string(),
-                scope: match scope {
-                    Some(scope) => Some(scope.to_string()),
-                    None => None,
-                },
+                scope: scope.map(|scope| scope.to_string()),
+                token_endpoint_auth_method: None,
+                private_key_file: None,
+                private_key_alg: None,
+                default_expires_in: None,
+                proxy: None,
+                ca_bundle: None,
+                client_cert: None,
+                client_key: None,
+                insecure_skip_verify: None,
+                connect_timeout: None,
+                read_timeout: None,
+                max_retries: None,
+                revocation_url: None,
+                introspection_url: None,
+                resources: None,
+                authorize_params: None,
+                token_params: None,
+                token_exchange: None,
             },
             tokens: None,
+            scoped_tokens: None,
+            profiles: None,
         };
 
         Ok(Config {
             config_dir: config_dir.to_string(),
             client_name: client_name.to_string(),
             path: path.to_owned(),
-            config: config,
+            client: config.client.clone(),
+            config,
+            settings,
+            profile: None,
+            migrated_from: None,
+            state_path: build_state_path(config_dir, client_name)?.0,
+            client_changed: true,
+            read_only: false,
         })
     }
 
     pub fn write(&self) -> BearerResult<()> {
         debug!("Writing configuration: {:?}", &self.config);
-        let filecontent = toml::to_string(&self.config);
-        if let Err(err) = filecontent {
-            return Err(BearerError::SerializationError(format!("Cannot serialize configuration \
-                                                                file {:?}: {:?}",
-                                                               &self.config,
-                                                               err)));
-        }
-        let filecontent = filecontent.unwrap();
-        let file = OpenOptions::new()
-            .mode(0o644)
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(self.path.as_str());
-
-        if let Err(err) = file {
-            return Err(BearerError::SerializationError(format!("Cannot open configuration file \
-                                                                {:?}: {:?}",
-                                                               &self.config,
-                                                               err)));
-        }
-        let mut file = file.unwrap();
-        let written = file.write_all(filecontent.as_bytes());
-        if let Err(err) = written {
-            return Err(BearerError::IOError(format!("IOError while writing file {}: {}",
-                                                    self.path.as_str(),
-                                                    err)));
+        if self.client_changed && self.read_only {
+            return Err(BearerError::ValueError(format!("Client {} is read-only, defined in {}",
+                                                       self.client_name,
+                                                       self.path)));
         }
-        Ok(())
+        if let (Some(version), true) = (self.migrated_from, self.client_changed) {
+            self.backup(version)?;
+        }
+        if self.client_changed {
+            write_file(self.path.as_str(), &self.config.without_tokens(), 0o644)?;
+        }
+        // The tokens are secrets, unlike the client definition.
+        write_file(self.state_path.as_str(), &TomlState::from_config(&self.config), 0o600)
     }
 
-    pub fn client(&self) -> ClientRef {
+    pub fn client(&self) -> ClientRef<'_> {
         ClientRef {
-            provider: self.config.client.provider.as_str(),
-            token_url: self.config.client.token_url.as_str(),
-            authorize_url: self.config.client.authorize_url.as_str(),
-            client_id: self.config.client.client_id.as_str(),
-            secret: self.config.client.secret.as_str(),
-            scope: match self.config.client.scope {
+            provider: self.client.provider.as_str(),
+            token_url: self.client.token_url.as_str(),
+            authorize_url: self.client.authorize_url.as_str(),
+            client_id: self.client.client_id.as_str(),
+            secret: self.client.secret.as_str(),
+            scope: match self.client.scope {
                 Some(ref scope) => Some(scope.as_str()),
                 None => None,
             },
+            token_endpoint_auth_method: self.client
+                .token_endpoint_auth_method
+                .as_ref()
+                .map(|method| method.as_str()),
+            private_key_file: self.client.private_key_file.as_ref().map(|path| path.as_str()),
+            private_key_alg: self.client.private_key_alg.as_ref().map(|alg| alg.as_str()),
+            default_expires_in: self.client.default_expires_in,
+            proxy: self.client.proxy.as_ref().map(|proxy| proxy.as_str()),
+            ca_bundle: self.client
+                .ca_bundle
+                .as_ref()
+                .or(self.settings.ca_bundle.as_ref())
+                .map(|path| path.as_str()),
+            client_cert: self.client
+                .client_cert
+                .as_ref()
+                .or(self.settings.client_cert.as_ref())
+                .map(|path| path.as_str()),
+            client_key: self.client
+                .client_key
+                .as_ref()
+                .or(self.settings.client_key.as_ref())
+                .map(|path| path.as_str()),
+            insecure_skip_verify: self.client
+                .insecure_skip_verify
+                .or(self.settings.insecure_skip_verify)
+                .unwrap_or(false),
+            connect_timeout: self.client.connect_timeout,
+            read_timeout: self.client.read_timeout,
+            max_retries: self.client.max_retries,
+            revocation_url: self.client.revocation_url.as_ref().map(|url| url.as_str()),
+            introspection_url: self.client.introspection_url.as_ref().map(|url| url.as_str()),
+            resources: self.client.resources.as_ref().map(|resources| resources.as_slice()),
+            authorize_params: self.client.authorize_params.as_ref(),
+            token_params: self.client.token_params.as_ref(),
+            token_exchange: self.client.token_exchange.as_ref(),
         }
     }
 
-    pub fn set_tokens(&mut self, tokens: Tokens) {
-        self.config.tokens = Some(tokens)
+    /// Names of the profiles defined in the client file.
+    pub fn profiles(&self) -> Vec<&str> {
+        match self.config.profiles {
+            Some(ref profiles) => profiles.keys().map(|name| name.as_str()).collect(),
+            None => Vec::new(),
+        }
     }
 
-    pub fn access_token(&self) -> Option<&str> {
-        match self.config.tokens {
-            Some(ref tokens) => Some(tokens.access_token.as_str()),
-            None => None,
+    /// The selected profile, None for the base client.
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_ref().map(|profile| profile.as_str())
+    }
+
+    /// Use the settings and the tokens of a profile instead of the base client.
+    pub fn select_profile(&mut self, profile: &str) -> BearerResult<()> {
+        if !self.profiles().contains(&profile) {
+            return Err(BearerError::ValueError(format!("Client {} has no profile {}",
+                                                       self.client_name,
+                                                       profile)));
         }
+        self.profile = Some(profile.to_string());
+        self.reload_client();
+        Ok(())
     }
 
-    pub fn expires_at(&self) -> Option<DateTime<UTC>> {
-        match self.config.tokens {
-            Some(ref tokens) => {
-                let expire_string = tokens.expires_at.to_string();
-                Some(expire_string.parse::<DateTime<UTC>>().unwrap())
+    fn active_profile(&self) -> Option<&Profile> {
+        match (self.profile.as_ref(), self.config.profiles.as_ref()) {
+            (Some(name), Some(profiles)) => profiles.get(name),
+            _ => None,
+        }
+    }
+
+    fn reload_client(&mut self) {
+        let client = match self.active_profile() {
+            Some(profile) => self.config.client.with_profile(profile),
+            None => self.config.client.clone(),
+        };
+        self.client = client;
+    }
+
+    fn active_profile_mut(&mut self) -> Option<&mut Profile> {
+        match (self.profile.as_ref(), self.config.profiles.as_mut()) {
+            (Some(name), Some(profiles)) => profiles.get_mut(name),
+            _ => None,
+        }
+    }
+
+    fn stored_tokens(&self) -> &Option<Tokens> {
+        match self.active_profile() {
+            Some(profile) => &profile.tokens,
+            None => &self.config.tokens,
+        }
+    }
+
+    fn stored_tokens_mut(&mut self) -> &mut Option<Tokens> {
+        if self.profile.is_some() {
+            return &mut self.active_profile_mut().unwrap().tokens;
+        }
+        &mut self.config.tokens
+    }
+
+    fn stored_scoped_tokens(&self) -> &Option<Vec<ScopedTokens>> {
+        match self.active_profile() {
+            Some(profile) => &profile.scoped_tokens,
+            None => &self.config.scoped_tokens,
+        }
+    }
+
+    fn stored_scoped_tokens_mut(&mut self) -> &mut Option<Vec<ScopedTokens>> {
+        if self.profile.is_some() {
+            return &mut self.active_profile_mut().unwrap().scoped_tokens;
+        }
+        &mut self.config.scoped_tokens
+    }
+
+    /// Extra parameters of the authorization request and of the token requests.
+    pub fn set_extra_params(&mut self,
+                            authorize_params: Option<BTreeMap<String, String>>,
+                            token_params: Option<BTreeMap<String, String>>) {
+        self.config.client.authorize_params = authorize_params;
+        self.config.client.token_params = token_params;
+        self.client_changed = true;
+        self.reload_client();
+    }
+
+    pub fn set_token_exchange(&mut self, token_exchange: Option<TokenExchange>) {
+        self.config.client.token_exchange = token_exchange;
+        self.client_changed = true;
+        self.reload_client();
+    }
+
+    pub fn set_auth_method(&mut self,
+                           method: Option<&str>,
+                           private_key_file: Option<&str>,
+                           private_key_alg: Option<&str>) {
+        self.config.client.token_endpoint_auth_method = method.map(|val| val.to_string());
+        self.config.client.private_key_file = private_key_file.map(|val| val.to_string());
+        self.config.client.private_key_alg = private_key_alg.map(|val| val.to_string());
+        self.client_changed = true;
+        self.reload_client();
+    }
+
+    pub fn set_revocation_url(&mut self, revocation_url: Option<String>) {
+        self.config.client.revocation_url = revocation_url;
+        self.client_changed = true;
+        self.reload_client();
+    }
+
+    /// A copy of the registration under another name, without any token.
+    pub fn copy(&self, client_name: &str) -> BearerResult<Self> {
+        let path = build_new_path(self.config_dir.as_str(), client_name)?;
+        let mut config = self.config.without_tokens();
+        config.version = Some(SCHEMA_VERSION);
+        Ok(Config {
+            config_dir: self.config_dir.clone(),
+            client_name: client_name.to_string(),
+            path,
+            config,
+            settings: self.settings.clone(),
+            profile: self.profile.clone(),
+            client: self.client.clone(),
+            migrated_from: None,
+            state_path: build_state_path(self.config_dir.as_str(), client_name)?.0,
+            client_changed: true,
+            read_only: false,
+        })
+    }
+
+    /// Update the `[client]` section, or the selected profile.
+    ///
+    /// Return the changed settings the tokens have been issued with, such as the
+    /// scope or the token url.
+    pub fn edit(&mut self, edit: &ClientEdit) -> BearerResult<Vec<&'static str>> {
+        for &(name, url) in [("authorize_url", &edit.authorize_url),
+                             ("token_url", &edit.token_url),
+                             ("revocation_url", &edit.revocation_url)]
+            .iter() {
+            if let Some(ref url) = *url {
+                validate_url(name, url.as_str())?;
+            }
+        }
+        if edit.client_id.as_ref().is_some_and(|client_id| client_id.is_empty()) {
+            return Err(BearerError::ValueError("The client id cannot be empty".to_string()));
+        }
+        let scope = edit.scope.as_ref().map(|scope| match scope.len() {
+            0 => None,
+            _ => Some(scope.to_string()),
+        });
+        if self.profile.is_some() && scope == Some(None) {
+            return Err(BearerError::ValueError("The scope of a profile cannot be removed"
+                .to_string()));
+        }
+
+        let mut stale = Vec::new();
+        {
+            let current = &self.client;
+            if edit.authorize_url.as_ref().is_some_and(|url| *url != current.authorize_url) {
+                stale.push("authorize_url");
+            }
+            if edit.token_url.as_ref().is_some_and(|url| *url != current.token_url) {
+                stale.push("token_url");
+            }
+            if edit.client_id.as_ref().is_some_and(|client_id| *client_id != current.client_id) {
+                stale.push("client_id");
+            }
+            if scope.as_ref().is_some_and(|scope| *scope != current.scope) {
+                stale.push("scope");
+            }
+        }
+        // A rotated secret does not revoke the tokens.
+        match self.active_profile_mut() {
+            Some(profile) => {
+                if let Some(ref authorize_url) = edit.authorize_url {
+                    profile.authorize_url = Some(authorize_url.to_string());
+                }
+                if let Some(ref token_url) = edit.token_url {
+                    profile.token_url = Some(token_url.to_string());
+                }
+                if let Some(ref client_id) = edit.client_id {
+                    profile.client_id = Some(client_id.to_string());
+                }
+                if let Some(scope) = scope {
+                    profile.scope = scope;
+                }
+                if let Some(ref revocation_url) = edit.revocation_url {
+                    profile.revocation_url = Some(revocation_url.to_string());
+                }
+                if let Some(ref secret) = edit.secret {
+                    profile.secret = Some(secret.to_string());
+                }
+            }
+            None => {
+                let client = &mut self.config.client;
+                if let Some(ref authorize_url) = edit.authorize_url {
+                    client.authorize_url = authorize_url.to_string();
+                }
+                if let Some(ref token_url) = edit.token_url {
+                    client.token_url = token_url.to_string();
+                }
+                if let Some(ref client_id) = edit.client_id {
+                    client.client_id = client_id.to_string();
+                }
+                if let Some(scope) = scope {
+                    client.scope = scope;
+                }
+                if let Some(ref revocation_url) = edit.revocation_url {
+                    client.revocation_url = Some(revocation_url.to_string());
+                }
+                if let Some(ref secret) = edit.secret {
+                    client.secret = secret.to_string();
+                }
+            }
+        }
+        self.client_changed = true;
+        self.reload_client();
+        Ok(stale)
+    }
+
+    /// Drop the tokens issued with the given settings: the ones of the selected
+    /// client or profile, and the ones of the profiles inheriting a setting from it.
+    pub fn clear_tokens(&mut self, fields: &[&str]) {
+        if fields.is_empty() {
+            return;
+        }
+        *self.stored_tokens_mut() = None;
+        *self.stored_scoped_tokens_mut() = None;
+        if self.profile.is_some() {
+            return;
+        }
+        if let Some(ref mut profiles) = self.config.profiles {
+            for profile in profiles.values_mut() {
+                if fields.iter().any(|field| !profile.overrides(field)) {
+                    profile.tokens = None;
+                    profile.scoped_tokens = None;
+                }
+            }
+        }
+    }
+
+    pub fn set_tokens(&mut self, tokens: Tokens) {
+        *self.stored_tokens_mut() = Some(tokens)
+    }
+
+    pub fn tokens_for(&self, key: &TokenKey) -> Option<&Tokens> {
+        if key.is_default() {
+            return self.stored_tokens().as_ref();
+        }
+        match *self.stored_scoped_tokens() {
+            Some(ref scoped) => {
+                scoped.iter().find(|scoped| scoped.matches(key)).map(|scoped| &scoped.tokens)
             }
             None => None,
         }
     }
 
-    pub fn expired(&self) -> Option<bool> {
-        match self.expires_at() {
-            Some(date) => {
-                let now: DateTime<UTC> = UTC::now();
-                debug!("{:?} > {:?}: {}", now, date, now > date);
-                Some(now > date)
+    pub fn set_tokens_for(&mut self, key: &TokenKey, mut tokens: Tokens) {
+        if key.is_default() {
+            return self.set_tokens(tokens);
+        }
+        // The refresh token belongs to the default tokens, it may have been rotated.
+        if let Some(rtoken) = tokens.refresh_token.take() {
+            if let Some(ref mut default) = *self.stored_tokens_mut() {
+                default.refresh_token = Some(rtoken);
             }
+        }
+        let scoped = self.stored_scoped_tokens_mut().get_or_insert(Vec::new());
+        scoped.retain(|scoped| !scoped.matches(key));
+        scoped.push(ScopedTokens {
+            scope: key.scope.map(|scope| scope.to_string()),
+            audience: key.audience.map(|audience| audience.to_string()),
+            resource: key.resource.map(|resource| resource.to_string()),
+            tokens,
+        });
+    }
+
+    /// Drop the tokens obtained with a narrowed scope, an audience or a resource.
+    pub fn clear_scoped_tokens(&mut self) {
+        *self.stored_scoped_tokens_mut() = None;
+    }
+
+    pub fn access_token(&self) -> Option<&str> {
+        match *self.stored_tokens() {
+            Some(ref tokens) => Some(tokens.access_token.as_str()),
             None => None,
         }
     }
 
+    pub fn expires_at(&self) -> Option<DateTime<UTC>> {
+        self.stored_tokens().as_ref().map(|tokens| tokens.expires_at())
+    }
+
+    pub fn expired(&self) -> Option<bool> {
+        self.stored_tokens().as_ref().map(|tokens| tokens.expired())
+    }
+
     pub fn refresh_token(&self) -> Option<&str> {
-        match self.config.tokens {
+        match *self.stored_tokens() {
             Some(ref tokens) => {
                 match tokens.refresh_token {
                     Some(ref token) => Some(token.as_str()),
@@ -248,7 +1175,6 @@ mod tests {
 
     use super::*;
     use std::fs;
-    use std::path::Path;
     use rand::{thread_rng, Rng};
 
     #[test]
@@ -269,6 +1195,27 @@ mod tests {
         assert_eq!(conf.refresh_token().is_none(), true);
     }
 
+    #[test]
+    fn test_http_settings() {
+        let settings = http_settings("src/tests/tls").unwrap();
+        assert_eq!(settings.ca_bundle, Some("/etc/ssl/certs/internal-ca.pem".to_string()));
+        assert_eq!(settings.client_cert, Some("/etc/ssl/certs/bearer.pem".to_string()));
+        assert_eq!(settings.insecure_skip_verify, false);
+
+        let settings = http_settings("src/tests/conf").unwrap();
+        assert_eq!(settings.ca_bundle, None);
+    }
+
+    #[test]
+    fn test_config_from_file_with_tls_settings() {
+        let conf = Config::from_file("src/tests/tls", "dummy").unwrap();
+        let client = conf.client();
+        assert_eq!(client.ca_bundle, Some("/etc/ssl/certs/internal-ca.pem"));
+        assert_eq!(client.client_cert, Some("/etc/ssl/certs/dummy.pem"));
+        assert_eq!(client.client_key, Some("/etc/ssl/private/dummy.key"));
+        assert_eq!(client.insecure_skip_verify, false);
+    }
+
     #[test]
     fn test_config_from_file_with_token() {
         let conf = Config::from_file("src/tests/conf", "dummy_with_tokens");
@@ -365,4 +1312,364 @@ mod tests {
         fs::remove_dir_all(tmpdir).unwrap();
     }
 
+    #[test]
+    fn test_config_scoped_tokens() {
+        let rnd: String = thread_rng().gen_ascii_chars().take(10).collect();
+        let tmpdir = format!("/tmp/test-bearer-{}", rnd);
+
+        let mut conf = Config::new(tmpdir.as_str(),
+                                   "client_name",
+                                   "provider",
+                                   "authorize_url",
+                                   "token_url",
+                                   "client_id",
+                                   "secret",
+                                   Some("read write"))
+            .unwrap();
+        conf.set_tokens(Tokens::new("abc", 60, Some("rtok")));
+
+        let key = TokenKey {
+            scope: Some("read"),
+            audience: Some("api"),
+            resource: None,
+        };
+        assert_eq!(conf.tokens_for(&key).is_none(), true);
+        conf.set_tokens_for(&key, Tokens::new("narrow", 60, Some("rotated")));
+        conf.write().unwrap();
+
+        let conf = Config::from_file(tmpdir.as_str(), "client_name").unwrap();
+        assert_eq!(conf.tokens_for(&key).unwrap().access_token, "narrow");
+        assert_eq!(conf.tokens_for(&key).unwrap().refresh_token, None);
+        assert_eq!(conf.tokens_for(&TokenKey::default()).unwrap().access_token, "abc");
+        assert_eq!(conf.refresh_token(), Some("rotated"));
+        let other = TokenKey {
+            scope: Some("read"),
+            audience: None,
+            resource: None,
+        };
+        assert_eq!(conf.tokens_for(&other).is_none(), true);
+        let resource = TokenKey {
+            resource: Some("https://api/"),
+            ..key.clone()
+        };
+        assert_eq!(conf.tokens_for(&resource).is_none(), true);
+
+        fs::remove_dir_all(tmpdir).unwrap();
+    }
+
+    #[test]
+    fn test_config_from_file_with_profile() {
+        let conf = Config::from_file_with_profile("src/tests/conf",
+                                                  "dummy_with_profiles",
+                                                  Some("staging"))
+            .unwrap();
+        assert_eq!(conf.profiles(), vec!["prod", "staging"]);
+        assert_eq!(conf.profile(), Some("staging"));
+        let client = conf.client();
+        assert_eq!(client.client_id, "129eff26");
+        assert_eq!(client.secret, "9e1c2b7a4f30");
+        assert_eq!(client.token_url, "http://127.0.0.1:1338/token");
+        assert_eq!(conf.access_token(), Some("7b3e0f1"));
+        assert_eq!(conf.refresh_token(), Some("a41f9c25e"));
+
+        let conf = Config::from_file_with_profile("src/tests/conf",
+                                                  "dummy_with_profiles",
+                                                  Some("prod"))
+            .unwrap();
+        assert_eq!(conf.client().secret, "00163e60d80f");
+        assert_eq!(conf.access_token(), None);
+
+        let conf = Config::from_file("src/tests/conf", "dummy_with_profiles").unwrap();
+        assert_eq!(conf.client().token_url, "http://127.0.0.1:1337/token");
+        assert_eq!(conf.access_token(), Some("56afe18"));
+
+        let conf = Config::from_file_with_profile("src/tests/conf", "dummy", Some("staging"));
+        assert_eq!(conf.unwrap_err(), BearerError::ValueError("".to_string()));
+    }
+
+    #[test]
+    fn test_config_profile_set_tokens() {
+        let rnd: String = thread_rng().gen_ascii_chars().take(10).collect();
+        let tmpdir = format!("/tmp/test-bearer-{}", rnd);
+        fs::create_dir_all(tmpdir.as_str()).unwrap();
+        fs::copy("src/tests/conf/dummy_with_profiles.toml",
+                 format!("{}/client_name.toml", tmpdir))
+            .unwrap();
+
+        let mut conf = Config::from_file_with_profile(tmpdir.as_str(),
+                                                      "client_name",
+                                                      Some("prod"))
+            .unwrap();
+        conf.set_tokens(Tokens::new("prodtok", 60, Some("prodrtok")));
+        conf.write().unwrap();
+
+        let conf = Config::from_file_with_profile(tmpdir.as_str(), "client_name", Some("prod"))
+            .unwrap();
+        assert_eq!(conf.access_token(), Some("prodtok"));
+        assert_eq!(conf.refresh_token(), Some("prodrtok"));
+        let conf = Config::from_file(tmpdir.as_str(), "client_name").unwrap();
+        assert_eq!(conf.access_token(), Some("56afe18"));
+
+        fs::remove_dir_all(tmpdir).unwrap();
+    }
+
+    #[test]
+    fn test_config_copy() {
+        let rnd: String = thread_rng().gen_ascii_chars().take(10).collect();
+        let tmpdir = format!("/tmp/test-bearer-{}", rnd);
+        fs::create_dir_all(tmpdir.as_str()).unwrap();
+        fs::copy("src/tests/conf/dummy_with_profiles.toml",
+                 format!("{}/client_name.toml", tmpdir))
+            .unwrap();
+
+        let conf = Config::from_file(tmpdir.as_str(), "client_name").unwrap();
+        let err = conf.copy("client_name");
+        assert_eq!(err.unwrap_err(), BearerError::ValueError("".to_string()));
+        let err = conf.copy("list");
+        assert_eq!(err.unwrap_err(), BearerError::ValueError("".to_string()));
+        conf.copy("copied").unwrap().write().unwrap();
+
+        let copied = Config::from_file(tmpdir.as_str(), "copied").unwrap();
+        assert_eq!(copied.client().client_id, conf.client().client_id);
+        assert_eq!(copied.profiles(), vec!["prod", "staging"]);
+        assert_eq!(copied.access_token(), None);
+        let copied = Config::from_file_with_profile(tmpdir.as_str(), "copied", Some("staging"))
+            .unwrap();
+        assert_eq!(copied.client().secret, "9e1c2b7a4f30");
+        assert_eq!(copied.access_token(), None);
+
+        fs::remove_dir_all(tmpdir).unwrap();
+    }
+
+    #[test]
+    fn test_config_edit() {
+        let mut conf = Config::from_file("src/tests/conf", "dummy_with_profiles").unwrap();
+
+        let edit = ClientEdit { secret: Some("newsecret".to_string()), ..ClientEdit::default() };
+        assert_eq!(conf.edit(&edit), Ok(vec![]));
+        assert_eq!(conf.client().secret, "newsecret");
+
+        let edit = ClientEdit { scope: Some("".to_string()), ..ClientEdit::default() };
+        assert_eq!(conf.edit(&edit), Ok(vec![]));
+        let edit = ClientEdit { scope: Some("read".to_string()), ..ClientEdit::default() };
+        assert_eq!(conf.edit(&edit), Ok(vec!["scope"]));
+        assert_eq!(conf.client().scope, Some("read"));
+
+        let edit = ClientEdit {
+            token_url: Some("http://127.0.0.1:1337/token".to_string()),
+            ..ClientEdit::default()
+        };
+        assert_eq!(conf.edit(&edit), Ok(vec![]));
+        let edit = ClientEdit { token_url: Some("not a url".to_string()), ..ClientEdit::default() };
+        assert_eq!(conf.edit(&edit), Err(BearerError::ValueError("".to_string())));
+        let edit = ClientEdit { client_id: Some("".to_string()), ..ClientEdit::default() };
+        assert_eq!(conf.edit(&edit), Err(BearerError::ValueError("".to_string())));
+
+        conf.clear_tokens(&["scope"]);
+        assert_eq!(conf.access_token(), None);
+        conf.select_profile("staging").unwrap();
+        assert_eq!(conf.access_token(), None);
+        assert_eq!(conf.client().scope, Some("read"));
+    }
+
+    #[test]
+    fn test_config_edit_clear_tokens() {
+        // The staging profile has its own token url
+        let mut conf = Config::from_file("src/tests/conf", "dummy_with_profiles").unwrap();
+        let edit = ClientEdit {
+            token_url: Some("http://127.0.0.1:1340/token".to_string()),
+            ..ClientEdit::default()
+        };
+        let stale = conf.edit(&edit).unwrap();
+        assert_eq!(stale, vec!["token_url"]);
+        conf.clear_tokens(stale.as_slice());
+        assert_eq!(conf.access_token(), None);
+        conf.select_profile("staging").unwrap();
+        assert_eq!(conf.access_token(), Some("7b3e0f1"));
+
+        let mut conf = Config::from_file_with_profile("src/tests/conf",
+                                                      "dummy_with_profiles",
+                                                      Some("staging"))
+            .unwrap();
+        let edit = ClientEdit { client_id: Some("4f0e2c".to_string()), ..ClientEdit::default() };
+        let stale = conf.edit(&edit).unwrap();
+        assert_eq!(stale, vec!["client_id"]);
+        conf.clear_tokens(stale.as_slice());
+        assert_eq!(conf.client().client_id, "4f0e2c");
+        assert_eq!(conf.access_token(), None);
+        let edit = ClientEdit { scope: Some("".to_string()), ..ClientEdit::default() };
+        assert_eq!(conf.edit(&edit), Err(BearerError::ValueError("".to_string())));
+
+        let conf = Config::from_file("src/tests/conf", "dummy_with_profiles").unwrap();
+        assert_eq!(conf.client().client_id, "129eff26");
+        assert_eq!(conf.access_token(), Some("56afe18"));
+    }
+
+    #[test]
+    fn test_config_migrate_v1() {
+        let rnd: String = thread_rng().gen_ascii_chars().take(10).collect();
+        let tmpdir = format!("/tmp/test-bearer-{}", rnd);
+        fs::create_dir_all(tmpdir.as_str()).unwrap();
+        fs::copy("src/tests/legacy/gandi.toml", format!("{}/gandi.toml", tmpdir)).unwrap();
+
+        let conf = Config::from_file(tmpdir.as_str(), "gandi").unwrap();
+        assert_eq!(conf.migrated_from(), Some(1));
+        // Loading a client does not rewrite it
+        let backup = format!("{}/gandi.toml.v1.bak", tmpdir);
+        assert!(!Path::new(backup.as_str()).exists());
+        assert_eq!(read_file(format!("{}/gandi.toml", tmpdir).as_str()).unwrap(),
+                   read_file("src/tests/legacy/gandi.toml").unwrap());
+        assert_eq!(conf.client().provider, "id.gandi.net");
+        assert_eq!(conf.client().authorize_url, "https://id.gandi.net/authorize");
+        assert_eq!(conf.client().token_url, "https://id.gandi.net/token");
+        assert_eq!(conf.client().client_id, "129eff26");
+        assert_eq!(conf.access_token(), Some("56afe18"));
+        assert_eq!(conf.refresh_token(), Some("d064258c7"));
+
+        conf.write().unwrap();
+        assert_eq!(read_file(backup.as_str()).unwrap(),
+                   read_file("src/tests/legacy/gandi.toml").unwrap());
+
+        let conf = Config::from_file(tmpdir.as_str(), "gandi").unwrap();
+        assert_eq!(conf.migrated_from(), None);
+        assert_eq!(conf.client().token_url, "https://id.gandi.net/token");
+        let content = read_file(format!("{}/gandi.toml", tmpdir).as_str()).unwrap();
+        assert!(String::from_utf8(content).unwrap().starts_with("version = 2\n"));
+
+        fs::remove_dir_all(tmpdir).unwrap();
+    }
+
+    #[test]
+    fn test_config_schema_version() {
+        let conf = Config::from_file("src/tests/conf", "dummy").unwrap();
+        assert_eq!(conf.migrated_from(), None);
+        assert_eq!(conf.config.version, Some(SCHEMA_VERSION));
+
+        let conf = Config::from_file("src/tests/legacy", "future");
+        assert_eq!(conf.unwrap_err(), BearerError::ValueError("".to_string()));
+    }
+
+    #[test]
+    fn test_config_token_store() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let rnd: String = thread_rng().gen_ascii_chars().take(10).collect();
+        let tmpdir = format!("/tmp/test-bearer-{}", rnd);
+        fs::create_dir_all(tmpdir.as_str()).unwrap();
+        let path = format!("{}/client_name.toml", tmpdir);
+        fs::copy("src/tests/conf/dummy_with_profiles.toml", path.as_str()).unwrap();
+
+        let state_path = format!("{}/state.toml", tmpdir);
+        let mut conf = Config::from_file(tmpdir.as_str(), "client_name").unwrap();
+        conf.state_path = state_path.clone();
+        conf.client_changed = conf.config.has_tokens();
+        conf.write().unwrap();
+
+        let client = String::from_utf8(read_file(path.as_str()).unwrap()).unwrap();
+        assert!(client.contains("[profiles.staging]"));
+        assert!(!client.contains("56afe18"));
+        let mode = fs::metadata(state_path.as_str()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let mut conf = Config::from_file(tmpdir.as_str(), "client_name").unwrap();
+        assert_eq!(conf.access_token(), None);
+        load_state(state_path.as_str()).unwrap().apply(&mut conf.config);
+        assert_eq!(conf.access_token(), Some("56afe18"));
+        conf.select_profile("staging").unwrap();
+        assert_eq!(conf.access_token(), Some("7b3e0f1"));
+        assert_eq!(conf.refresh_token(), Some("a41f9c25e"));
+
+        fs::remove_dir_all(tmpdir).unwrap();
+    }
+
+    #[test]
+    fn test_config_move_tokens() {
+        let rnd: String = thread_rng().gen_ascii_chars().take(10).collect();
+        let tmpdir = format!("/tmp/test-bearer-{}", rnd);
+        let state_dir = format!("{}/state", tmpdir);
+        fs::create_dir_all(tmpdir.as_str()).unwrap();
+        let path = format!("{}/client_name.toml", tmpdir);
+        fs::copy("src/tests/conf/dummy_with_tokens.toml", path.as_str()).unwrap();
+
+        let conf = Config::from_dirs(tmpdir.as_str(),
+                                     "src/tests/legacy",
+                                     state_dir.as_str(),
+                                     "client_name")
+            .unwrap();
+        assert_eq!(conf.has_changes(), true);
+        assert_eq!(conf.access_token(), Some("56afe18"));
+        conf.write().unwrap();
+
+        let client = String::from_utf8(read_file(path.as_str()).unwrap()).unwrap();
+        assert!(!client.contains("56afe18"));
+        let conf = Config::from_dirs(tmpdir.as_str(),
+                                     "src/tests/legacy",
+                                     state_dir.as_str(),
+                                     "client_name")
+            .unwrap();
+        assert_eq!(conf.has_changes(), false);
+        assert_eq!(conf.access_token(), Some("56afe18"));
+
+        fs::remove_dir_all(tmpdir).unwrap();
+    }
+
+    #[test]
+    fn test_is_read_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let rnd: String = thread_rng().gen_ascii_chars().take(10).collect();
+        let tmpdir = format!("/tmp/test-bearer-{}", rnd);
+        fs::create_dir_all(tmpdir.as_str()).unwrap();
+        let path = format!("{}/client_name.toml", tmpdir);
+        fs::copy("src/tests/conf/dummy.toml", path.as_str()).unwrap();
+        assert_eq!(is_read_only(path.as_str()), false);
+
+        // The permission bits do not apply to root
+        fs::set_permissions(path.as_str(), fs::Permissions::from_mode(0o444)).unwrap();
+        let root = unsafe { libc::getuid() == 0 };
+        assert_eq!(is_read_only(path.as_str()), !root);
+        let conf = Config::from_file(tmpdir.as_str(), "client_name").unwrap();
+        assert_eq!(conf.read_only(), !root);
+
+        fs::remove_dir_all(tmpdir).unwrap();
+    }
+
+    #[test]
+    fn test_config_from_system_dir() {
+        let rnd: String = thread_rng().gen_ascii_chars().take(10).collect();
+        let tmpdir = format!("/tmp/test-bearer-{}", rnd);
+        let state_dir = format!("{}/state", tmpdir);
+        fs::create_dir_all(tmpdir.as_str()).unwrap();
+
+        let mut conf = Config::from_dirs(tmpdir.as_str(),
+                                         "src/tests/conf",
+                                         state_dir.as_str(),
+                                         "dummy_with_tokens")
+            .unwrap();
+        assert_eq!(conf.read_only(), true);
+        assert_eq!(conf.state_path, format!("{}/dummy_with_tokens.toml", state_dir));
+        assert_eq!(conf.client().client_id, "129eff26");
+        conf.set_auth_method(Some("client_secret_basic"), None, None);
+        assert_eq!(conf.write(), Err(BearerError::ValueError("".to_string())));
+
+        // A client of the config dir shadows the one of the system dir
+        fs::copy("src/tests/conf/dummy.toml",
+                 format!("{}/dummy_with_tokens.toml", tmpdir))
+            .unwrap();
+        let conf = Config::from_dirs(tmpdir.as_str(),
+                                     "src/tests/conf",
+                                     state_dir.as_str(),
+                                     "dummy_with_tokens")
+            .unwrap();
+        assert_eq!(conf.read_only(), false);
+        assert_eq!(conf.access_token(), None);
+
+        let conf = Config::from_dirs(tmpdir.as_str(),
+                                     "src/tests/conf",
+                                     state_dir.as_str(),
+                                     "not_exists");
+        assert_eq!(conf.unwrap_err(), BearerError::ValueError("".to_string()));
+
+        fs::remove_dir_all(tmpdir).unwrap();
+    }
 }
@@ -0,0 +1,68 @@
+//! Minimal reading of JSON Web Tokens, the signature is not verified.
+//!
+//! Access tokens are opaque for the client, we only look at them to
+//! guess their expiration date.
+
+use base64;
+use chrono::datetime::DateTime;
+use chrono::offset::TimeZone;
+use chrono::offset::utc::UTC;
+use serde_json;
+use serde_json::Value;
+
+
+/// Decode the claims of a JWT, None if the token is not a JWT.
+pub fn claims(token: &str) -> Option<Value> {
+    let parts = token.split('.').collect::<Vec<&str>>();
+    if parts.len() != 3 {
+        return None;
+    }
+    let payload = parts[1].trim_right_matches('=');
+    let payload = match base64::decode_config(payload, base64::URL_SAFE_NO_PAD) {
+        Ok(payload) => payload,
+        Err(_) => return None,
+    };
+    match serde_json::from_slice::<Value>(payload.as_slice()) {
+        Ok(claims) => {
+            if claims.is_object() {
+                Some(claims)
+            } else {
+                None
+            }
+        }
+        Err(_) => None,
+    }
+}
+
+
+/// Expiration date from the `exp` claim of a JWT, None if it is not a valid date.
+pub fn expiration(token: &str) -> Option<DateTime<UTC>> {
+    claims(token)
+        .and_then(|claims| claims.get("exp").and_then(|exp| exp.as_f64()))
+        .and_then(|exp| UTC.timestamp_opt(exp as i64, 0).single())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expiration() {
+        // {"alg":"HS256","typ":"JWT"}.{"sub":"me","exp":1700000000}
+        let token = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.\
+                     eyJzdWIiOiJtZSIsImV4cCI6MTcwMDAwMDAwMH0.c2lnbmF0dXJl";
+        assert_eq!(expiration(token),
+                   Some("2023-11-14T22:13:20+00:00".parse::<DateTime<UTC>>().unwrap()));
+    }
+
+    #[test]
+    fn test_expiration_not_a_jwt() {
+        assert_eq!(expiration("56afe18"), None);
+        assert_eq!(expiration("a.b.c"), None);
+        // {"sub":"me"}
+        assert_eq!(expiration("eyJhbGciOiJub25lIn0.eyJzdWIiOiJtZSJ9."), None);
+        // {"exp":9999999999999}, a timestamp in milliseconds
+        assert_eq!(expiration("eyJhbGciOiJub25lIn0.eyJleHAiOjk5OTk5OTk5OTk5OTl9."), None);
+    }
+}
@@ -1,4 +1,7 @@
-pub mod oauth2;
+pub mod client_auth;
+pub mod http;
+mod jwt;
 pub mod oauth2client;
 pub mod path;
 pub mod providers;
+pub mod tokens;
@@ -1,52 +1,246 @@
 
+use std::collections::HashMap;
+
 use serde_json;
-use cabot::{RequestBuilder, Client};
+use serde_json::Value;
+use chrono::Duration;
+use chrono::datetime::DateTime;
+use chrono::offset::TimeZone;
+use chrono::offset::utc::UTC;
+use url::form_urlencoded;
 use url::form_urlencoded::Serializer as URLSerializer;
 
-use super::super::results::{BearerResult, BearerError};
-use super::super::config::{Tokens, ClientRef};
+use super::super::results::{BearerResult, BearerError, OAuth2ErrorResponse};
+use super::super::config::{Tokens, TokenKey, ClientRef};
+use super::client_auth;
+use super::http;
+use super::jwt;
+
+/// Lifetime of access tokens when the server does not tell, in seconds.
+pub const DEFAULT_EXPIRES_IN: usize = 900;
+
+const TOKEN_EXCHANGE_GRANT: &'static str = "urn:ietf:params:oauth:grant-type:token-exchange";
+pub const ACCESS_TOKEN_TYPE: &'static str = "urn:ietf:params:oauth:token-type:access_token";
+
+
+/// Parameters of a token exchange request, see RFC 8693 section 2.1.
+#[derive(Debug, Default)]
+pub struct TokenExchangeRequest<'a> {
+    pub subject_token: &'a str,
+    pub subject_token_type: &'a str,
+    pub actor_token: Option<&'a str>,
+    pub actor_token_type: Option<&'a str>,
+    pub audience: Option<&'a str>,
+    pub resource: Option<&'a str>,
+    pub scope: Option<&'a str>,
+    pub requested_token_type: Option<&'a str>,
+}
 
 
-#[derive(Deserialize)]
-pub struct JsonToken {
+#[derive(Debug, Deserialize)]
+pub struct TokenResponse {
     pub access_token: String,
-    pub expires_in: Option<usize>,
+    pub expires_in: Option<Value>,
+    /// Absolute expiration date, `expires_on` is sent by Azure AD.
+    pub expires_at: Option<Value>,
+    pub expires_on: Option<Value>,
     pub refresh_token: Option<String>,
 }
 
 
-fn fetch_token(token_url: &str, form: &[u8]) -> BearerResult<Tokens> {
-    println!("Fetchin tokens from {}", token_url);
-    let request = RequestBuilder::new(token_url)
-        .set_http_method("POST")
-        .add_header("Content-Type: application/x-www-form-urlencoded")
-        .set_body(form)
-        .build();
+/// Read a number that may be sent as a string.
+fn value_as_i64(value: &Value) -> Option<i64> {
+    match *value {
+        Value::String(ref val) => val.trim().parse::<f64>().ok().map(|val| val as i64),
+        ref val => val.as_f64().map(|val| val as i64),
+    }
+}
 
-    let request = request.unwrap();
 
-    let client = Client::new();
-    let response = client.execute(&request).unwrap();
+/// Read a date sent as a timestamp or as an RFC 3339 string.
+fn value_as_datetime(value: &Value) -> Option<DateTime<UTC>> {
+    match value_as_i64(value) {
+        Some(timestamp) => UTC.timestamp_opt(timestamp, 0).single(),
+        None => value.as_str().and_then(|val| val.parse::<DateTime<UTC>>().ok()),
+    }
+}
+
+
+/// The date a number of seconds after `now`, None if it is out of range.
+fn seconds_after(now: DateTime<UTC>, seconds: i64) -> Option<DateTime<UTC>> {
+    let max = Duration::max_value().num_seconds();
+    if seconds > max || seconds < -max {
+        return None;
+    }
+    now.checked_add_signed(Duration::seconds(seconds))
+}
 
-    let code = response.status_code();
-    let data = response.body_as_string().unwrap();
 
+impl TokenResponse {
+    /// Expiration date of the access token.
+    ///
+    /// The `exp` claim of a JWT access token is preferred, then the `expires_in`,
+    /// `expires_at` and `expires_on` of the response, then the `default_lifetime`.
+    /// Values out of the range of the dates are skipped.
+    pub fn expires_at(&self, default_lifetime: usize) -> DateTime<UTC> {
+        if let Some(exp) = jwt::expiration(self.access_token.as_str()) {
+            return exp;
+        }
+        let now: DateTime<UTC> = UTC::now();
+        let expires_in = self.expires_in.as_ref().and_then(value_as_i64);
+        if let Some(expires_at) = expires_in.and_then(|secs| seconds_after(now, secs)) {
+            return expires_at;
+        }
+        let absolute = self.expires_at.as_ref().or(self.expires_on.as_ref());
+        if let Some(expires_at) = absolute.and_then(value_as_datetime) {
+            return expires_at;
+        }
+        now + Duration::seconds(default_lifetime as i64)
+    }
+}
+
+
+fn is_form_encoded(content_type: Option<&str>, data: &str) -> bool {
+    match content_type {
+        Some(ctype) if ctype.contains("json") => false,
+        Some(ctype) if ctype.contains("application/x-www-form-urlencoded") => true,
+        // text/plain or no content type at all, guess from the body
+        _ => !data.trim_left().starts_with('{'),
+    }
+}
+
+
+fn parse_form_token(token_url: &str, code: usize, data: &str) -> BearerResult<TokenResponse> {
+    let mut params: HashMap<String, String> = form_urlencoded::parse(data.trim().as_bytes())
+        .into_owned()
+        .collect();
+    if let Some(error) = params.remove("error") {
+        return Err(BearerError::OAuth2Error(OAuth2ErrorResponse {
+            error,
+            error_description: params.remove("error_description"),
+            error_uri: params.remove("error_uri"),
+            status: Some(code),
+        }));
+    }
     if code >= 300 {
-        return Err(BearerError::OAuth2Error(format!(r#"Server did not return a valid response \
-while consuming auth code, expected `2XX`, found `{}`: {}"#,
-                                                    code,
-                                                    data)));
+        return Err(BearerError::OAuth2Error(OAuth2ErrorResponse::from_unexpected_response(code,
+                                                                                         data)));
+    }
+    match params.remove("access_token") {
+        Some(access_token) => {
+            Ok(TokenResponse {
+                access_token,
+                expires_in: params.remove("expires_in").map(Value::String),
+                expires_at: params.remove("expires_at").map(Value::String),
+                expires_on: params.remove("expires_on").map(Value::String),
+                refresh_token: params.remove("refresh_token"),
+            })
+        }
+        None => {
+            Err(BearerError::ParseError(format!("Cannot parse token response from {}: missing \
+access_token",
+                                                token_url)))
+        }
     }
+}
 
 
-    let token: JsonToken = serde_json::from_str(data.as_str()).unwrap();
+fn parse_json_token(token_url: &str, code: usize, data: &str) -> BearerResult<TokenResponse> {
+    if code >= 300 {
+        let err = match OAuth2ErrorResponse::from_json(code, data) {
+            Some(err) => err,
+            None => OAuth2ErrorResponse::from_unexpected_response(code, data),
+        };
+        return Err(BearerError::OAuth2Error(err));
+    }
 
-    Ok(Tokens::new(token.access_token.as_str(),
-                   token.expires_in.unwrap_or(900),
-                   match token.refresh_token {
-                       Some(ref tok) => Some(tok.as_str()),
-                       None => None,
-                   }))
+    match serde_json::from_str(data) {
+        Ok(token) => Ok(token),
+        Err(err) => {
+            // Some providers return errors with a 200 status code
+            if let Some(err) = OAuth2ErrorResponse::from_json(code, data) {
+                return Err(BearerError::OAuth2Error(err));
+            }
+            Err(BearerError::ParseError(format!("Cannot parse token response from {}: {:?}",
+                                                token_url,
+                                                err)))
+        }
+    }
+}
+
+
+/// Parse a token endpoint response according to its content type.
+fn parse_token_response(token_url: &str,
+                        content_type: Option<&str>,
+                        code: usize,
+                        data: &str)
+                        -> BearerResult<TokenResponse> {
+    if is_form_encoded(content_type, data) {
+        parse_form_token(token_url, code, data)
+    } else {
+        parse_json_token(token_url, code, data)
+    }
+}
+
+
+fn fetch_token(client: &ClientRef, params: &[(&str, &str)]) -> BearerResult<Tokens> {
+    let token_url = client.token_url;
+    debug!("Fetching tokens from {}", token_url);
+
+    let auth = client_auth::authenticate(client)?;
+    let mut form = URLSerializer::new(String::new());
+    for &(ref key, ref value) in auth.params.iter() {
+        form.append_pair(key.as_str(), value.as_str());
+    }
+    for &(key, value) in params {
+        form.append_pair(key, value);
+    }
+    if let Some(resources) = client.resources {
+        // A resource requested for a given token replaces the ones of the client.
+        if !params.iter().any(|&(name, _)| name == "resource") {
+            for resource in resources.iter() {
+                form.append_pair("resource", resource.as_str());
+            }
+        }
+    }
+    if let Some(token_params) = client.token_params {
+        // The parameters of the grant take precedence.
+        for (key, value) in token_params.iter() {
+            if !params.iter().any(|&(name, _)| name == key.as_str()) {
+                form.append_pair(key.as_str(), value.as_str());
+            }
+        }
+    }
+    let form = form.finish();
+
+    let mut headers = vec!["Content-Type: application/x-www-form-urlencoded",
+                           "Accept: application/json"];
+    for header in auth.headers.iter() {
+        headers.push(header.as_str());
+    }
+
+    let mut settings = http::HttpSettings::from_client(client);
+    // The authorization code is consumed by the first request that reaches the server
+    settings.resend = !params.contains(&("grant_type", "authorization_code"));
+    let response = http::execute(&settings,
+                                 "POST",
+                                 token_url,
+                                 headers.as_slice(),
+                                 form.as_bytes())?;
+
+    let code = response.status_code();
+    let data = response.body_as_string()?;
+    let content_type = response.header("Content-Type");
+
+    let token = parse_token_response(token_url, content_type, code, data.as_str())?;
+
+    let expires_at = token.expires_at(client.default_expires_in.unwrap_or(DEFAULT_EXPIRES_IN));
+    Ok(Tokens::expiring_at(token.access_token.as_str(),
+                           expires_at,
+                           match token.refresh_token {
+                               Some(ref tok) => Some(tok.as_str()),
+                               None => None,
+                           }))
 
 }
 
@@ -56,56 +250,182 @@ pub fn from_authcode(client: &ClientRef,
                      callback_uri: &str)
                      -> BearerResult<Tokens> {
 
-    let form = URLSerializer::new(String::new())
-        .append_pair("client_id", client.client_id)
-        .append_pair("client_secret", client.secret)
-        .append_pair("code", authcode)
-        .append_pair("redirect_uri", callback_uri)
-        .append_pair("grant_type", "authorization_code")
-        .finish();
-
-    fetch_token(client.token_url, form.as_bytes())
+    fetch_token(client,
+                &[("code", authcode),
+                  ("redirect_uri", callback_uri),
+                  ("grant_type", "authorization_code")])
 }
 
 
-pub fn from_refresh_token(client: &ClientRef, refresh_token: &str) -> BearerResult<Tokens> {
-
-    let form = URLSerializer::new(String::new())
-        .append_pair("client_id", client.client_id)
-        .append_pair("client_secret", client.secret)
-        .append_pair("refresh_token", refresh_token)
-        .append_pair("grant_type", "refresh_token")
-        .finish();
+/// Consume a refresh token.
+///
+/// A key that is not the default one narrows the scope or request an audience,
+/// if the provider supports it.
+pub fn from_refresh_token(client: &ClientRef,
+                          refresh_token: &str,
+                          key: &TokenKey)
+                          -> BearerResult<Tokens> {
 
-    let mut token = fetch_token(client.token_url, form.as_bytes())?;
+    let mut params = vec![("refresh_token", refresh_token), ("grant_type", "refresh_token")];
+    if let Some(scope) = key.scope {
+        params.push(("scope", scope));
+    }
+    if let Some(audience) = key.audience {
+        params.push(("audience", audience));
+    }
+    if let Some(resource) = key.resource {
+        params.push(("resource", resource));
+    }
+    let mut token = fetch_token(client, params.as_slice())?;
     if token.refresh_token.is_none() {
         token.refresh_token = Some(refresh_token.to_string());
     }
     Ok(token)
 }
 
+pub fn from_token_exchange(client: &ClientRef,
+                           request: &TokenExchangeRequest)
+                           -> BearerResult<Tokens> {
+
+    let mut params = vec![("grant_type", TOKEN_EXCHANGE_GRANT),
+                          ("subject_token", request.subject_token),
+                          ("subject_token_type", request.subject_token_type)];
+    if let Some(actor_token) = request.actor_token {
+        params.push(("actor_token", actor_token));
+        params.push(("actor_token_type", request.actor_token_type.unwrap_or(ACCESS_TOKEN_TYPE)));
+    }
+    if let Some(audience) = request.audience {
+        params.push(("audience", audience));
+    }
+    if let Some(resource) = request.resource {
+        params.push(("resource", resource));
+    }
+    if let Some(scope) = request.scope {
+        params.push(("scope", scope));
+    }
+    if let Some(requested_token_type) = request.requested_token_type {
+        params.push(("requested_token_type", requested_token_type));
+    }
+    fetch_token(client, params.as_slice())
+}
+
+
+/// Post a token to the revocation or introspection endpoint of the client.
+fn post_token(client: &ClientRef,
+              url: &str,
+              token: &str,
+              token_type_hint: &str)
+              -> BearerResult<http::Response> {
+    let auth = client_auth::authenticate(client)?;
+    let mut form = URLSerializer::new(String::new());
+    for &(ref key, ref value) in auth.params.iter() {
+        form.append_pair(key.as_str(), value.as_str());
+    }
+    form.append_pair("token", token);
+    form.append_pair("token_type_hint", token_type_hint);
+    let form = form.finish();
+
+    let mut headers = vec!["Content-Type: application/x-www-form-urlencoded"];
+    for header in auth.headers.iter() {
+        headers.push(header.as_str());
+    }
+
+    let settings = http::HttpSettings::from_client(client);
+    http::execute(&settings, "POST", url, headers.as_slice(), form.as_bytes())
+}
+
+
+fn error_response(response: &http::Response) -> BearerError {
+    let code = response.status_code();
+    let data = match response.body_as_string() {
+        Ok(data) => data,
+        Err(err) => return err,
+    };
+    let err = OAuth2ErrorResponse::from_json(code, data.as_str())
+        .unwrap_or_else(|| OAuth2ErrorResponse::from_unexpected_response(code, data.as_str()));
+    BearerError::OAuth2Error(err)
+}
+
+
+/// Revoke a token at the revocation endpoint of the client, see RFC 7009.
+pub fn revoke_token(client: &ClientRef, token: &str, token_type_hint: &str) -> BearerResult<()> {
+    let revocation_url = match client.revocation_url {
+        Some(url) => url,
+        None => {
+            return Err(BearerError::ValueError("No revocation_url for the client".to_string()));
+        }
+    };
+    debug!("Revoking {} at {}", token_type_hint, revocation_url);
+
+    let response = post_token(client, revocation_url, token, token_type_hint)?;
+    if response.status_code() == 200 {
+        return Ok(());
+    }
+    Err(error_response(&response))
+}
+
+
+/// State of a token returned by the introspection endpoint, see RFC 7662 section 2.2.
+#[derive(Debug, Deserialize)]
+pub struct TokenIntrospection {
+    pub active: bool,
+    pub scope: Option<String>,
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub token_type: Option<String>,
+    pub exp: Option<Value>,
+    pub sub: Option<String>,
+    pub aud: Option<Value>,
+}
+
+impl TokenIntrospection {
+    /// Expiration date of the token, if the server tells.
+    pub fn expires_at(&self) -> Option<DateTime<UTC>> {
+        self.exp.as_ref().and_then(value_as_datetime)
+    }
+}
+
+
+/// Ask the introspection endpoint of the client the state of a token, see RFC 7662.
+pub fn introspect_token(client: &ClientRef,
+                        token: &str,
+                        token_type_hint: &str)
+                        -> BearerResult<TokenIntrospection> {
+    let introspection_url = match client.introspection_url {
+        Some(url) => url,
+        None => {
+            return Err(BearerError::ValueError("No introspection_url for the client"
+                .to_string()));
+        }
+    };
+    debug!("Introspecting {} at {}", token_type_hint, introspection_url);
+
+    let response = post_token(client, introspection_url, token, token_type_hint)?;
+    if response.status_code() != 200 {
+        return Err(error_response(&response));
+    }
+    let data = response.body_as_string()?;
+    serde_json::from_str::<TokenIntrospection>(data.as_str()).map_err(|err| {
+        BearerError::ParseError(format!("Cannot parse introspection response from {}: {:?}",
+                                        introspection_url,
+                                        err))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::prelude::*;
 
-    use std::thread;
-    use std::time;
-    use std::net::TcpListener;
-    use rand::{thread_rng, Rng};
+    use std::collections::BTreeMap;
 
     use super::*;
     use super::super::super::config::ClientRef;
+    use super::super::super::test_server::serve;
 
     #[test]
     fn test_from_authcode() {
 
-        let mut rng = thread_rng();
-        let server_port: usize = rng.gen_range(3000, 9000);
-        let server_addr = format!("127.0.0.1:{}", server_port);
-        let token_url = format!("http://127.0.0.1:{}", server_port);
-
-        let authservhandler = thread::spawn(move || {
-            let authorization_server = TcpListener::bind(server_addr.as_str()).unwrap();
+        let (server_port, authservhandler) = serve(move |authorization_server| {
             let stream = authorization_server.incoming().next().unwrap();
             let mut stream = stream.unwrap();
             let tokens = r#"{
@@ -124,16 +444,11 @@ mod tests {
             stream.write(resp.as_bytes()).unwrap();
         });
 
-        let dur = time::Duration::from_millis(700);
-        thread::sleep(dur);
+        let token_url = format!("http://127.0.0.1:{}", server_port);
 
         let client = ClientRef {
-            provider: "",
             token_url: token_url.as_str(),
-            authorize_url: "",
-            client_id: "",
-            secret: "",
-            scope: None,
+            ..ClientRef::default()
         };
 
         let tokens = from_authcode(&client, "authcode", "http://127.0.0.1/callback");
@@ -149,13 +464,7 @@ mod tests {
     #[test]
     fn test_from_refresh_token() {
 
-        let mut rng = thread_rng();
-        let server_port: usize = rng.gen_range(3000, 9000);
-        let server_addr = format!("127.0.0.1:{}", server_port);
-        let token_url = format!("http://127.0.0.1:{}", server_port);
-
-        let authservhandler = thread::spawn(move || {
-            let authorization_server = TcpListener::bind(server_addr.as_str()).unwrap();
+        let (server_port, authservhandler) = serve(move |authorization_server| {
             let stream = authorization_server.incoming().next().unwrap();
             let mut stream = stream.unwrap();
             let tokens = r#"{
@@ -172,19 +481,14 @@ mod tests {
             stream.write(resp.as_bytes()).unwrap();
         });
 
-        let dur = time::Duration::from_millis(700);
-        thread::sleep(dur);
+        let token_url = format!("http://127.0.0.1:{}", server_port);
 
         let client = ClientRef {
-            provider: "",
             token_url: token_url.as_str(),
-            authorize_url: "",
-            client_id: "",
-            secret: "",
-            scope: None,
+            ..ClientRef::default()
         };
 
-        let tokens = from_refresh_token(&client, "refresh_token");
+        let tokens = from_refresh_token(&client, "refresh_token", &TokenKey::default());
         assert_eq!(tokens.is_err(), false);
         let tokens = tokens.unwrap();
         assert_eq!(tokens.access_token, "atok");
@@ -193,5 +497,247 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_from_refresh_token_token_params() {
+
+        let (server_port, authservhandler) = serve(move |authorization_server| {
+            let mut stream = authorization_server.incoming().next().unwrap().unwrap();
+            let mut buffer = [0; 4096];
+            stream.read(&mut buffer[..]).unwrap();
+            let request = String::from_utf8_lossy(&buffer).to_string();
+            assert!(request.contains("&scope=read&resource=https%3A%2F%2Fapi%2F&x=y\0"));
+
+            let tokens = r#"{"access_token": "atok", "expires_in": 42}"#;
+            let content_len = format!("Content-Length: {}", tokens.len());
+            let resp = vec!["HTTP/1.0 200 Ok",
+                            "Content-Type: application/json",
+                            content_len.as_str(),
+                            "",
+                            tokens];
+            stream.write(resp.join("\r\n").as_bytes()).unwrap();
+        });
+
+        let token_url = format!("http://127.0.0.1:{}", server_port);
+
+        let mut token_params = BTreeMap::new();
+        token_params.insert("scope".to_string(), "ignored".to_string());
+        token_params.insert("resource".to_string(), "https://other/".to_string());
+        token_params.insert("x".to_string(), "y".to_string());
+        let resources = vec!["https://first/".to_string(), "https://second/".to_string()];
+        let client = ClientRef {
+            token_url: token_url.as_str(),
+            resources: Some(resources.as_slice()),
+            token_params: Some(&token_params),
+            ..ClientRef::default()
+        };
+        let key = TokenKey {
+            scope: Some("read"),
+            audience: None,
+            resource: Some("https://api/"),
+        };
+
+        let tokens = from_refresh_token(&client, "refresh_token", &key).unwrap();
+        assert_eq!(tokens.access_token, "atok");
+        authservhandler.join().unwrap();
+    }
+
+    #[test]
+    fn test_from_authcode_form_encoded() {
+
+        let (server_port, authservhandler) = serve(move |authorization_server| {
+            let stream = authorization_server.incoming().next().unwrap();
+            let mut stream = stream.unwrap();
+            let mut buffer = [0; 4096];
+            stream.read(&mut buffer[..]).unwrap();
+            let request = String::from_utf8_lossy(&buffer).to_string();
+            assert!(request.contains("Accept: application/json\r\n"));
+
+            let tokens = "access_token=atok&scope=user&token_type=bearer";
+            let content_len = format!("Content-Length: {}", tokens.len());
+            let resp = vec!["HTTP/1.0 200 Ok",
+                            "Content-Type: application/x-www-form-urlencoded; charset=utf-8",
+                            content_len.as_str(),
+                            "",
+                            tokens];
+            let resp = resp.join("\r\n");
+
+            stream.write(resp.as_bytes()).unwrap();
+        });
+
+        let token_url = format!("http://127.0.0.1:{}", server_port);
+
+        let client = ClientRef {
+            token_url: token_url.as_str(),
+            ..ClientRef::default()
+        };
+
+        let tokens = from_authcode(&client, "authcode", "http://127.0.0.1/callback");
+        assert_eq!(tokens.is_err(), false);
+        let tokens = tokens.unwrap();
+        assert_eq!(tokens.access_token, "atok");
+        assert_eq!(tokens.refresh_token, None);
+        authservhandler.join().unwrap();
+
+    }
+
+    #[test]
+    fn test_token_response_expires_at() {
+        let token = parse_token_response("", None, 200, r#"{"access_token": "a",
+"expires_in": "3600"}"#)
+            .unwrap();
+        let expires_in = token.expires_at(900).signed_duration_since(UTC::now());
+        assert!(expires_in > Duration::seconds(3590) && expires_in <= Duration::seconds(3600));
+
+        let token = parse_token_response("", None, 200, r#"{"access_token": "a",
+"expires_on": "1700000000"}"#)
+            .unwrap();
+        assert_eq!(token.expires_at(900), UTC.timestamp(1700000000, 0));
+
+        let token = parse_token_response("", None, 200, r#"{"access_token": "a",
+"expires_at": "2023-11-14T22:13:20+00:00"}"#)
+            .unwrap();
+        assert_eq!(token.expires_at(900), UTC.timestamp(1700000000, 0));
+
+        let token = parse_token_response("", None, 200, r#"{"access_token": "a"}"#).unwrap();
+        let expires_in = token.expires_at(86400).signed_duration_since(UTC::now());
+        assert!(expires_in > Duration::seconds(86390) && expires_in <= Duration::seconds(86400));
+
+        // Out of range values fall back to the next source
+        let token = parse_token_response("", None, 200, r#"{"access_token": "a",
+"expires_in": 9223372036854775807, "expires_on": "1700000000"}"#)
+            .unwrap();
+        assert_eq!(token.expires_at(900), UTC.timestamp(1700000000, 0));
+        let token = parse_token_response("", None, 200, r#"{"access_token": "a",
+"expires_on": 9999999999999}"#)
+            .unwrap();
+        let expires_in = token.expires_at(900).signed_duration_since(UTC::now());
+        assert!(expires_in > Duration::seconds(890) && expires_in <= Duration::seconds(900));
+        let token = parse_token_response("", None, 200, r#"{"access_token":
+"eyJhbGciOiJIUzI1NiJ9.eyJleHAiOjk5OTk5OTk5OTk5OTl9.c2ln", "expires_in": 3600}"#)
+            .unwrap();
+        let expires_in = token.expires_at(900).signed_duration_since(UTC::now());
+        assert!(expires_in > Duration::seconds(3590) && expires_in <= Duration::seconds(3600));
+
+        // The exp claim of a JWT wins
+        let token = parse_token_response("", None, 200, r#"{"access_token":
+"eyJhbGciOiJIUzI1NiJ9.eyJleHAiOjE3MDAwMDAwMDB9.c2ln", "expires_in": 3600}"#)
+            .unwrap();
+        assert_eq!(token.expires_at(900), UTC.timestamp(1700000000, 0));
+    }
 
+    #[test]
+    fn test_parse_token_response() {
+        let token =
+            parse_token_response("", Some("text/plain"), 200, "access_token=a&expires_in=60")
+                .unwrap();
+        assert_eq!(token.access_token, "a");
+        assert_eq!(token.expires_in, Some(Value::String("60".to_string())));
+
+        let token = parse_token_response("", None, 200, r#"{"access_token": "b"}"#).unwrap();
+        assert_eq!(token.access_token, "b");
+        assert_eq!(token.expires_in, None);
+
+        let err = parse_token_response("",
+                                       Some("application/x-www-form-urlencoded"),
+                                       200,
+                                       "error=bad_verification_code&error_description=The+code")
+            .unwrap_err();
+        match err {
+            BearerError::OAuth2Error(ref err) => {
+                assert_eq!(err.error, "bad_verification_code");
+                assert_eq!(err.error_description, Some("The code".to_string()));
+            }
+            _ => panic!("Unexpected error {:?}", err),
+        }
+
+        let err = parse_token_response("", Some("application/json"), 200, "{}").unwrap_err();
+        assert_eq!(err, BearerError::ParseError("".to_string()));
+    }
+
+    #[test]
+    fn test_revoke_token() {
+
+        let (server_port, authservhandler) = serve(move |authorization_server| {
+            for status in ["HTTP/1.0 200 Ok", "HTTP/1.0 400 Bad Request"].iter() {
+                let mut stream = authorization_server.incoming().next().unwrap().unwrap();
+                let mut buffer = [0; 4096];
+                stream.read(&mut buffer[..]).unwrap();
+                let request = String::from_utf8_lossy(&buffer).to_string();
+                assert!(request.starts_with("POST /revoke "));
+                assert!(request.contains("&token=rtok&token_type_hint=refresh_token\0"));
+
+                let body = r#"{"error": "unsupported_token_type"}"#;
+                let content_len = format!("Content-Length: {}", body.len());
+                let resp = vec![*status,
+                                "Content-Type: application/json",
+                                content_len.as_str(),
+                                "",
+                                body];
+                stream.write(resp.join("\r\n").as_bytes()).unwrap();
+            }
+        });
+
+        let revocation_url = format!("http://127.0.0.1:{}/revoke", server_port);
+
+        let client = ClientRef {
+            client_id: "cid",
+            revocation_url: Some(revocation_url.as_str()),
+            ..ClientRef::default()
+        };
+        assert_eq!(revoke_token(&client, "rtok", "refresh_token"), Ok(()));
+        let err = revoke_token(&client, "rtok", "refresh_token").unwrap_err();
+        assert_eq!(format!("{}", err), "unsupported_token_type [HTTP 400]");
+        authservhandler.join().unwrap();
+
+        let client = ClientRef::default();
+        assert!(revoke_token(&client, "rtok", "refresh_token").is_err());
+    }
+
+    #[test]
+    fn test_introspect_token() {
+
+        let (server_port, authservhandler) = serve(move |authorization_server| {
+            let responses = [("HTTP/1.0 200 Ok",
+                              r#"{"active": true, "scope": "read", "exp": 1500000000}"#),
+                             ("HTTP/1.0 200 Ok", r#"{"active": false}"#),
+                             ("HTTP/1.0 401 Unauthorized", r#"{"error": "invalid_client"}"#)];
+            for &(status, body) in responses.iter() {
+                let mut stream = authorization_server.incoming().next().unwrap().unwrap();
+                let mut buffer = [0; 4096];
+                stream.read(&mut buffer[..]).unwrap();
+                let request = String::from_utf8_lossy(&buffer).to_string();
+                assert!(request.starts_with("POST /introspect "));
+                assert!(request.contains("&token=atok&token_type_hint=access_token\0"));
+
+                let content_len = format!("Content-Length: {}", body.len());
+                let resp = vec![status,
+                                "Content-Type: application/json",
+                                content_len.as_str(),
+                                "",
+                                body];
+                stream.write(resp.join("\r\n").as_bytes()).unwrap();
+            }
+        });
+
+        let introspection_url = format!("http://127.0.0.1:{}/introspect", server_port);
+
+        let client = ClientRef {
+            client_id: "cid",
+            introspection_url: Some(introspection_url.as_str()),
+            ..ClientRef::default()
+        };
+        let introspection = introspect_token(&client, "atok", "access_token").unwrap();
+        assert_eq!(introspection.active, true);
+        assert_eq!(introspection.scope, Some("read".to_string()));
+        assert_eq!(introspection.expires_at(), Some(UTC.timestamp(1500000000, 0)));
+        let introspection = introspect_token(&client, "atok", "access_token").unwrap();
+        assert_eq!(introspection.active, false);
+        assert_eq!(introspection.expires_at(), None);
+        let err = introspect_token(&client, "atok", "access_token").unwrap_err();
+        assert_eq!(format!("{}", err), "invalid_client [HTTP 401]");
+        authservhandler.join().unwrap();
+
+        let client = ClientRef::default();
+        assert!(introspect_token(&client, "atok", "access_token").is_err());
+    }
 }
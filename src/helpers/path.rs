@@ -1,10 +1,38 @@
+use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::vec::Vec;
 use tilde_expand::tilde_expand;
 
 use super::super::results::{BearerResult, BearerError};
 
+/// Config dir of the command line, one file per client.
+///
+/// Used if neither `BEARER_CONFIG_DIR` nor `XDG_CONFIG_HOME` are set.
+pub const DEFAULT_CONFIG_DIR: &'static str = "~/.config/bearer";
+
+/// Token store of the user, one directory per config dir.
+///
+/// Used if neither `BEARER_STATE_DIR` nor `XDG_STATE_HOME` are set.
+pub const DEFAULT_STATE_DIR: &'static str = "~/.local/state/bearer";
+
+/// Read-only client definitions shared by the users of the host.
+pub const SYSTEM_CONFIG_DIR: &'static str = "/etc/bearer";
+
+/// Global settings shared by all the clients of a config dir.
+pub const SETTINGS_FILE: &'static str = "settings.toml";
+
+/// Registry of the OAuth2.0 providers known by name.
+pub const PROVIDERS_FILE: &'static str = "providers.toml";
+
+/// Files of the config dir that are not client registrations.
+const RESERVED_FILES: [&'static str; 2] = [SETTINGS_FILE, PROVIDERS_FILE];
+
+/// Subcommands of bearer, a client named like one is shadowed by the command.
+pub const COMMAND_NAMES: [&'static str; 11] = ["register", "list", "refresh", "header", "remove",
+                                                "edit", "rename", "copy", "migrate-dir",
+                                                "completions", "help"];
+
 
 fn expand_path(config_dir: &str) -> BearerResult<String> {
     let config_dir_expanded = tilde_expand(config_dir.as_bytes());
@@ -19,7 +47,150 @@ fn expand_path(config_dir: &str) -> BearerResult<String> {
 }
 
 
+/// Directory from an environment variable, then from a XDG base directory.
+fn dir_from_env<F>(getenv: F, var: &str, xdg_var: &str, default: &str) -> String
+    where F: Fn(&str) -> Option<String>
+{
+    match getenv(var) {
+        Some(ref dir) if !dir.is_empty() => return dir.to_string(),
+        _ => {}
+    }
+    // Relative paths are invalid in XDG variables and must be ignored.
+    match getenv(xdg_var) {
+        Some(ref base) if base.starts_with('/') => {
+            format!("{}/bearer", base.trim_right_matches('/'))
+        }
+        _ => default.to_string(),
+    }
+}
+
+
+/// Config dir used when none is given on the command line.
+pub fn default_config_dir() -> String {
+    dir_from_env(|name| env::var(name).ok(),
+                 "BEARER_CONFIG_DIR",
+                 "XDG_CONFIG_HOME",
+                 DEFAULT_CONFIG_DIR)
+}
+
+
+/// Token store of the user.
+#[cfg(not(test))]
+pub fn default_state_dir() -> String {
+    dir_from_env(|name| env::var(name).ok(),
+                 "BEARER_STATE_DIR",
+                 "XDG_STATE_HOME",
+                 DEFAULT_STATE_DIR)
+}
+
+/// The tests never read nor write the tokens of the user.
+#[cfg(test)]
+pub fn default_state_dir() -> String {
+    "/tmp/test-bearer-state".to_string()
+}
+
+
+/// Directory storing the tokens of the clients of a config dir.
+///
+/// Every config dir, the default one included, has its own directory in the token
+/// store of the user, so that two clients of the same name never share their tokens.
+pub fn state_dir(config_dir: &str) -> BearerResult<String> {
+    namespaced_state_dir(default_state_dir().as_str(), config_dir)
+}
+
+
+/// Directory of a config dir in a token store, named after its absolute path.
+fn namespaced_state_dir(state_dir: &str, config_dir: &str) -> BearerResult<String> {
+    let config_dir = PathBuf::from(expand_path(config_dir)?);
+    let config_dir = if config_dir.is_absolute() {
+        config_dir
+    } else {
+        match env::current_dir() {
+            Ok(cwd) => cwd.join(config_dir),
+            Err(err) => {
+                return Err(BearerError::IOError(format!("Cannot get the current directory: {}",
+                                                        err)))
+            }
+        }
+    };
+    // Remove the ./ and the trailing slash, the same dir must have a single name
+    let config_dir = config_dir.components().collect::<PathBuf>();
+    match config_dir.to_str() {
+        Some(config_dir) => {
+            Ok(format!("{}/dirs/{}",
+                       state_dir.trim_right_matches('/'),
+                       config_dir.replace('%', "%25").replace('/', "%2F")))
+        }
+        None => {
+            Err(BearerError::UTF8EncodingError(format!("Could not build the state dir of {:?}",
+                                                       config_dir)))
+        }
+    }
+}
+
+
+/// Path of the token file of a client, and whether it exists.
+pub fn build_state_path(config_dir: &str, client_name: &str) -> BearerResult<(String, bool)> {
+    build_state_file_path(state_dir(config_dir)?.as_str(), client_name)
+}
+
+
+/// Path of the token file of a client in a token store, and whether it exists.
+pub fn build_state_file_path(state_dir: &str, client_name: &str) -> BearerResult<(String, bool)> {
+    build_file_path(state_dir, format!("{}.toml", client_name).as_str())
+}
+
+
+/// Path of a client defined in a system dir, None if it is not defined there.
+///
+/// Unlike the config dir, the system dir is never created.
+pub fn find_system_client(system_dir: &str, client_name: &str) -> BearerResult<Option<String>> {
+    let filename = format!("{}.toml", client_name);
+    if RESERVED_FILES.contains(&filename.as_str()) {
+        return Err(BearerError::ValueError(format!("{} is a reserved name", client_name)));
+    }
+    let path = Path::new(system_dir).join(filename);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    match path.to_str() {
+        Some(path) => Ok(Some(path.to_string())),
+        None => {
+            Err(BearerError::UTF8EncodingError(format!("Could not build path with system dir {}",
+                                                       system_dir)))
+        }
+    }
+}
+
+
 pub fn build_path(config_dir: &str, client_name: &str) -> BearerResult<(String, bool)> {
+    let filename = format!("{}.toml", client_name);
+    if RESERVED_FILES.contains(&filename.as_str()) {
+        return Err(BearerError::ValueError(format!("{} is a reserved name", client_name)));
+    }
+    build_file_path(config_dir, filename.as_str())
+}
+
+
+/// Path of the file of a client that does not exist yet.
+///
+/// Existing clients named like a command can still be used, removed and renamed.
+pub fn build_new_path(config_dir: &str, client_name: &str) -> BearerResult<String> {
+    if COMMAND_NAMES.contains(&client_name) {
+        return Err(BearerError::ValueError(format!("{} is a command name, it cannot name a \
+                                                    client",
+                                                   client_name)));
+    }
+    let (path, exists) = build_path(config_dir, client_name)?;
+    if exists {
+        return Err(BearerError::ValueError(format!("Client {} already registered", client_name)));
+    }
+    Ok(path)
+}
+
+
+/// Path of a file in the config dir, and whether it exists.
+pub fn build_file_path(config_dir: &str, filename: &str) -> BearerResult<(String, bool)> {
 
     let config_dir_expanded = expand_path(config_dir)?;
     let path = config_dir_expanded.clone();
@@ -38,15 +209,15 @@ pub fn build_path(config_dir: &str, client_name: &str) -> BearerResult<(String,
     }
 
 
-    let path = path.join(format!("{}.toml", client_name));
+    let path = path.join(filename);
 
     match path.to_str() {
         Some(string) => Ok((string.to_string(), path.is_file())),
         None => {
             Err(BearerError::UTF8EncodingError(format!(r#"Could not build path with config dir {} \
-and client {}"#,
+and file {}"#,
                                                        config_dir_expanded,
-                                                       client_name)))
+                                                       filename)))
         }
     }
 }
@@ -63,7 +234,7 @@ pub fn list_clients(config_dir: &str) -> BearerResult<Vec<String>> {
     let paths = fs::read_dir(path).unwrap();
     let mut ret =
         paths.map(|pth| pth.unwrap().path().file_name().unwrap().to_str().unwrap().to_string())
-            .filter(|pth| pth.ends_with(".toml"))
+            .filter(|pth| pth.ends_with(".toml") && !RESERVED_FILES.contains(&pth.as_str()))
             .map(|pth| pth.as_str()[..pth.len() - 5].to_string())
             .collect::<Vec<String>>();
     ret.sort();
@@ -71,10 +242,23 @@ pub fn list_clients(config_dir: &str) -> BearerResult<Vec<String>> {
 }
 
 
+/// Clients of the config dir and of the system dir, without duplicates.
+pub fn list_all_clients(config_dir: &str, system_dir: &str) -> BearerResult<Vec<String>> {
+    let mut clients = list_clients(config_dir)?;
+    if Path::new(system_dir).is_dir() {
+        clients.extend(list_clients(system_dir)?);
+        clients.sort();
+        clients.dedup();
+    }
+    Ok(clients)
+}
+
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use std::collections::HashMap;
     use std::fs;
     use std::path::Path;
     use rand::{thread_rng, Rng};
@@ -93,6 +277,25 @@ mod tests {
         assert!(path.ends_with("src/tests/conf/not_exists.toml"));
     }
 
+    #[test]
+    fn test_build_path_reserved() {
+        let err = build_path("src/tests/conf", "settings");
+        assert_eq!(err.unwrap_err(), BearerError::ValueError("".to_string()));
+    }
+
+    #[test]
+    fn test_build_new_path() {
+        for name in COMMAND_NAMES.iter() {
+            let err = build_new_path("src/tests/conf", name);
+            assert_eq!(err.unwrap_err(), BearerError::ValueError("".to_string()));
+        }
+        let err = build_new_path("src/tests/conf", "dummy");
+        assert_eq!(err.unwrap_err(), BearerError::ValueError("".to_string()));
+        let path = build_new_path("src/tests/conf", "lister").unwrap();
+        assert!(path.ends_with("src/tests/conf/lister.toml"));
+        assert_eq!(build_path("src/tests/conf", "list").unwrap().1, false);
+    }
+
     #[test]
     fn test_build_path_create_dir() {
         let rnd: String = thread_rng().gen_ascii_chars().take(10).collect();
@@ -122,7 +325,7 @@ mod tests {
     fn test_list_clients_ok() {
         let clients = list_clients("src/tests/conf").unwrap();
         assert_eq!(clients.as_slice(),
-                   &["dummy", "dummy_with_tokens", "invalid"])
+                   &["dummy", "dummy_with_profiles", "dummy_with_tokens", "invalid"])
     }
 
     #[test]
@@ -132,4 +335,62 @@ mod tests {
         let err = err.unwrap_err();
         assert_eq!(err, BearerError::ValueError("".to_string()))
     }
+
+    #[test]
+    fn test_dir_from_env() {
+        let mut vars = HashMap::new();
+        let dir = |vars: &HashMap<&str, &str>| {
+            dir_from_env(|name| vars.get(name).map(|val| val.to_string()),
+                         "BEARER_CONFIG_DIR",
+                         "XDG_CONFIG_HOME",
+                         DEFAULT_CONFIG_DIR)
+        };
+        assert_eq!(dir(&vars), "~/.config/bearer");
+        vars.insert("XDG_CONFIG_HOME", "relative/config");
+        assert_eq!(dir(&vars), "~/.config/bearer");
+        vars.insert("XDG_CONFIG_HOME", "/home/me/.cfg/");
+        assert_eq!(dir(&vars), "/home/me/.cfg/bearer");
+        vars.insert("BEARER_CONFIG_DIR", "");
+        assert_eq!(dir(&vars), "/home/me/.cfg/bearer");
+        vars.insert("BEARER_CONFIG_DIR", "~/dotfiles/bearer");
+        assert_eq!(dir(&vars), "~/dotfiles/bearer");
+    }
+
+    #[test]
+    fn test_namespaced_state_dir() {
+        assert_eq!(namespaced_state_dir("/tmp/state/", "/home/me/dotfiles/bearer/").unwrap(),
+                   "/tmp/state/dirs/%2Fhome%2Fme%2Fdotfiles%2Fbearer");
+        let cwd = env::current_dir().unwrap();
+        let expected = format!("/tmp/state/dirs/{}%2Fsrc%2Ftests%2Fconf",
+                               cwd.to_str().unwrap().replace('/', "%2F"));
+        assert_eq!(namespaced_state_dir("/tmp/state", "./src/tests/conf").unwrap(), expected);
+        assert_eq!(namespaced_state_dir("/tmp/state", "src/tests/conf").unwrap(), expected);
+        assert!(namespaced_state_dir("/tmp/state", "src/tests/legacy").unwrap() != expected);
+    }
+
+    #[test]
+    fn test_state_dir() {
+        let state_dir = state_dir("/tmp/bearer/").unwrap();
+        assert_eq!(state_dir, "/tmp/test-bearer-state/dirs/%2Ftmp%2Fbearer");
+        assert!(state_dir != self::state_dir(default_config_dir().as_str()).unwrap());
+    }
+
+    #[test]
+    fn test_find_system_client() {
+        let path = find_system_client("src/tests/conf", "dummy").unwrap().unwrap();
+        assert_eq!(path, "src/tests/conf/dummy.toml");
+        assert_eq!(find_system_client("src/tests/conf", "not_exists"), Ok(None));
+        assert_eq!(find_system_client("not/an/existing/directory", "dummy"), Ok(None));
+        assert!(find_system_client("src/tests/conf", "providers").is_err());
+    }
+
+    #[test]
+    fn test_list_all_clients() {
+        let clients = list_all_clients("src/tests/legacy", "src/tests/conf").unwrap();
+        assert_eq!(clients.as_slice(),
+                   &["dummy", "dummy_with_profiles", "dummy_with_tokens", "future", "gandi",
+                     "invalid"]);
+        let clients = list_all_clients("src/tests/legacy", "not/an/existing/directory").unwrap();
+        assert_eq!(clients.as_slice(), &["future", "gandi"]);
+    }
 }
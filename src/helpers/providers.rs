@@ -1,44 +1,303 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
 
-#[derive(Debug, Clone)]
+use serde_json;
+use serde_json::Value;
+use toml;
+
+use super::http;
+use super::http::HttpSettings;
+use super::path::{build_file_path, PROVIDERS_FILE, SYSTEM_CONFIG_DIR};
+use super::super::results::{BearerResult, BearerError};
+
+
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct Provider {
-    pub name: &'static str,
-    pub authorize_url: &'static str,
-    pub token_url: &'static str,
+    #[serde(default)]
+    pub name: String,
+    pub authorize_url: Option<String>,
+    pub token_url: Option<String>,
+    /// Token revocation endpoint, see RFC 7009.
+    pub revocation_url: Option<String>,
+    /// Scope proposed while registering a client.
+    pub scope: Option<String>,
+    pub token_endpoint_auth_method: Option<String>,
+    /// OpenID Connect discovery document, used when the urls are not set.
+    pub discovery_url: Option<String>,
+    /// Extra parameters of the authorization request.
+    pub authorize_params: Option<BTreeMap<String, String>>,
+    /// Extra parameters of the token requests.
+    pub token_params: Option<BTreeMap<String, String>>,
+}
+
+type Providers = HashMap<String, Provider>;
+
+
+fn builtin(name: &str, authorize_url: &str, token_url: &str) -> Provider {
+    Provider {
+        name: name.to_string(),
+        authorize_url: Some(authorize_url.to_string()),
+        token_url: Some(token_url.to_string()),
+        ..Provider::default()
+    }
 }
 
-type Providers = HashMap<&'static str, Provider>;
+
+/// Built-in provider with `{placeholder}` in its urls.
+fn template(name: &str, authorize_url: &str, token_url: &str, scope: &str) -> Provider {
+    Provider { scope: Some(scope.to_string()), ..builtin(name, authorize_url, token_url) }
+}
 
 
 fn known_providers() -> Providers {
-    let mut providers: Providers = HashMap::with_capacity(3);
-    providers.insert("gandi",
+    let mut providers: Providers = HashMap::with_capacity(8);
+    providers.insert("gandi".to_string(),
+                     builtin("Gandi",
+                             "https://id.gandi.net/authorize",
+                             "https://id.gandi.net/token"));
+    providers.insert("github".to_string(),
+                     builtin("Github",
+                             "https://github.com/login/oauth/authorize",
+                             "https://github.com/login/oauth/access_token"));
+    // Google only issues a refresh token with those parameters.
+    let mut google_params = BTreeMap::new();
+    google_params.insert("access_type".to_string(), "offline".to_string());
+    google_params.insert("prompt".to_string(), "consent".to_string());
+    providers.insert("google".to_string(),
                      Provider {
-                         name: "Gandi",
-                         authorize_url: "https://id.gandi.net/authorize",
-                         token_url: "https://id.gandi.net/token",
-                     });
-    providers.insert("github",
-                     Provider {
-                         name: "Github",
-                         authorize_url: "https://github.com/login/oauth/authorize",
-                         token_url: "https://github.com/login/oauth/access_token",
-                     });
-    providers.insert("google",
-                     Provider {
-                         name: "Google",
-                         authorize_url: "https://accounts.google.com/o/oauth2/v2/auth",
-                         token_url: "https://www.googleapis.com/oauth2/v4/token",
+                         revocation_url: Some("https://oauth2.googleapis.com/revoke".to_string()),
+                         authorize_params: Some(google_params),
+                         ..builtin("Google",
+                                   "https://accounts.google.com/o/oauth2/v2/auth",
+                                   "https://www.googleapis.com/oauth2/v4/token")
                      });
+    providers.insert("azure".to_string(),
+                     template("Azure",
+                              "https://login.microsoftonline.com/{tenant}/oauth2/v2.0/authorize",
+                              "https://login.microsoftonline.com/{tenant}/oauth2/v2.0/token",
+                              "openid offline_access"));
+    providers.insert("okta".to_string(),
+                     template("Okta",
+                              "https://{domain}/oauth2/default/v1/authorize",
+                              "https://{domain}/oauth2/default/v1/token",
+                              "openid offline_access"));
+    providers.insert("auth0".to_string(),
+                     template("Auth0",
+                              "https://{domain}/authorize",
+                              "https://{domain}/oauth/token",
+                              "openid offline_access"));
+    providers.insert("keycloak".to_string(),
+                     template("Keycloak",
+                              "https://{domain}/realms/{realm}/protocol/openid-connect/auth",
+                              "https://{domain}/realms/{realm}/protocol/openid-connect/token",
+                              "openid"));
+    providers.insert("cognito".to_string(),
+                     template("Cognito",
+                              "https://{domain}/oauth2/authorize",
+                              "https://{domain}/oauth2/token",
+                              "openid"));
     providers
 }
 
 
-pub fn get_provider(name: &str) -> Option<Provider> {
+/// Add the providers of a registry file, they replace the ones with the same key.
+fn load_file(path: &str, providers: &mut Providers) -> BearerResult<()> {
+    let mut buf: Vec<u8> = Vec::new();
+    let read = File::open(path).and_then(|mut file| file.read_to_end(&mut buf));
+    if let Err(err) = read {
+        return Err(BearerError::IOError(format!("Cannot read providers file {}: {}", path, err)));
+    }
+    let registry: Result<HashMap<String, Provider>, toml::de::Error> =
+        toml::from_slice(buf.as_slice());
+    let registry = match registry {
+        Ok(registry) => registry,
+        Err(err) => {
+            return Err(BearerError::ParseError(format!("Cannot parse providers file {}: {:?}",
+                                                       path,
+                                                       err)))
+        }
+    };
+    for (key, mut provider) in registry {
+        if provider.name.is_empty() {
+            provider.name = key.clone();
+        }
+        if provider.discovery_url.is_none() &&
+           (provider.authorize_url.is_none() || provider.token_url.is_none()) {
+            return Err(BearerError::ValueError(format!("Provider {} in {} requires urls or a \
+discovery_url",
+                                                       key,
+                                                       path)));
+        }
+        providers.insert(key.to_lowercase(), provider);
+    }
+    Ok(())
+}
+
+
+/// The known providers, then the registry of the system dir, then the one of the
+/// config dir.
+fn load_providers(config_dir: &str, system_dir: &str) -> BearerResult<Providers> {
+    let mut providers = known_providers();
+    let system_path = Path::new(system_dir).join(PROVIDERS_FILE);
+    if let (true, Some(system_path)) = (system_path.is_file(), system_path.to_str()) {
+        load_file(system_path, &mut providers)?;
+    }
+    let (path, exists) = build_file_path(config_dir, PROVIDERS_FILE)?;
+    if exists {
+        load_file(path.as_str(), &mut providers)?;
+    }
+    Ok(providers)
+}
+
+
+pub fn get_provider(config_dir: &str, name: &str) -> BearerResult<Option<Provider>> {
+    find_provider(config_dir, SYSTEM_CONFIG_DIR, name)
+}
+
+
+fn find_provider(config_dir: &str, system_dir: &str, name: &str) -> BearerResult<Option<Provider>> {
     let name = name.to_lowercase();
-    match known_providers().get(name.as_str()) {
-        Some(provider) => Some(provider.clone()),
-        None => None,
+    let providers = load_providers(config_dir, system_dir)?;
+    Ok(providers.get(name.as_str()).cloned())
+}
+
+
+/// Read the endpoints from an OpenID Connect discovery document.
+fn discover(settings: &HttpSettings, discovery_url: &str) -> BearerResult<(String, String)> {
+    debug!("Fetching discovery document {}", discovery_url);
+    let response = http::execute(settings,
+                                 "GET",
+                                 discovery_url,
+                                 &["Accept: application/json"],
+                                 b"")?;
+    if response.status_code() != 200 {
+        return Err(BearerError::ValueError(format!("Cannot fetch discovery document {}: HTTP \
+{}",
+                                                   discovery_url,
+                                                   response.status_code())));
+    }
+    let body = response.body_as_string()?;
+    let document: Value = match serde_json::from_str(body.as_str()) {
+        Ok(document) => document,
+        Err(err) => {
+            return Err(BearerError::ParseError(format!("Cannot parse discovery document {}: {}",
+                                                       discovery_url,
+                                                       err)))
+        }
+    };
+    let endpoint = |name: &str| {
+        match document.get(name).and_then(|val| val.as_str()) {
+            Some(url) => Ok(url.to_string()),
+            None => {
+                Err(BearerError::ValueError(format!("Missing {} in discovery document {}",
+                                                    name,
+                                                    discovery_url)))
+            }
+        }
+    };
+    Ok((endpoint("authorization_endpoint")?, endpoint("token_endpoint")?))
+}
+
+
+/// Names between braces in a url template, in order of appearance.
+fn find_placeholders(template: &str, placeholders: &mut Vec<String>) {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        match rest.find('}') {
+            Some(end) => {
+                let name = &rest[..end];
+                if !placeholders.iter().any(|known| known == name) {
+                    placeholders.push(name.to_string());
+                }
+                rest = &rest[end + 1..];
+            }
+            None => break,
+        }
+    }
+}
+
+
+/// Whether a value can fill a placeholder without changing the host or the path of the url.
+///
+/// Only the domain may have a port.
+fn valid_value(name: &str, value: &str) -> bool {
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || "._-".contains(c) ||
+                               (c == ':' && name == "domain");
+    !value.is_empty() && value != "." && value != ".." && value.chars().all(valid_char)
+}
+
+
+fn substitute(template: &str, values: &HashMap<String, String>) -> BearerResult<String> {
+    let mut placeholders = Vec::new();
+    find_placeholders(template, &mut placeholders);
+    let mut result = template.to_string();
+    for name in placeholders {
+        match values.get(name.as_str()) {
+            Some(value) if !valid_value(name.as_str(), value.as_str()) => {
+                return Err(BearerError::ValueError(format!("Invalid {} {:?}", name, value)))
+            }
+            Some(value) => result = result.replace(format!("{{{}}}", name).as_str(), value),
+            None => {
+                return Err(BearerError::ValueError(format!("Missing {} in {}", name, template)))
+            }
+        }
+    }
+    Ok(result)
+}
+
+
+impl Provider {
+    /// Placeholders of the urls, such as `tenant` or `realm`, to be filled.
+    pub fn placeholders(&self) -> Vec<String> {
+        let mut placeholders = Vec::new();
+        for url in [&self.authorize_url,
+                    &self.token_url,
+                    &self.revocation_url,
+                    &self.discovery_url]
+            .iter() {
+            if let Some(ref url) = **url {
+                find_placeholders(url.as_str(), &mut placeholders);
+            }
+        }
+        placeholders
+    }
+
+    /// The provider with the placeholders of its urls replaced.
+    pub fn with_values(&self, values: &HashMap<String, String>) -> BearerResult<Provider> {
+        let fill = |url: &Option<String>| -> BearerResult<Option<String>> {
+            match *url {
+                Some(ref url) => Ok(Some(substitute(url.as_str(), values)?)),
+                None => Ok(None),
+            }
+        };
+        Ok(Provider {
+            authorize_url: fill(&self.authorize_url)?,
+            token_url: fill(&self.token_url)?,
+            revocation_url: fill(&self.revocation_url)?,
+            discovery_url: fill(&self.discovery_url)?,
+            ..self.clone()
+        })
+    }
+
+    /// The authorize url and the token url, discovered if they are not set.
+    pub fn endpoints(&self, settings: &HttpSettings) -> BearerResult<(String, String)> {
+        match (self.authorize_url.as_ref(), self.token_url.as_ref(), self.discovery_url.as_ref()) {
+            (Some(authorize_url), Some(token_url), _) => {
+                Ok((authorize_url.to_string(), token_url.to_string()))
+            }
+            (authorize_url, token_url, Some(discovery_url)) => {
+                let (discovered_authorize_url, discovered_token_url) =
+                    discover(settings, discovery_url.as_str())?;
+                Ok((authorize_url.cloned().unwrap_or(discovered_authorize_url),
+                    token_url.cloned().unwrap_or(discovered_token_url)))
+            }
+            _ => {
+                Err(BearerError::ValueError(format!("Provider {} has no urls", self.name)))
+            }
+        }
     }
 }
 
@@ -46,10 +305,16 @@ pub fn get_provider(name: &str) -> Option<Provider> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::super::test_server::serve;
+
+    use std::fs;
+    use rand::{thread_rng, Rng};
+
+    const NO_SYSTEM_DIR: &'static str = "not/an/existing/directory";
 
     #[test]
     fn test_get_provier_known_provider() {
-        let provider = get_provider("GANDI");
+        let provider = find_provider("src/tests/conf", NO_SYSTEM_DIR, "GANDI").unwrap();
         assert_eq!(provider.is_some(), true);
         let provider = provider.unwrap();
         assert_eq!(provider.name, "Gandi");
@@ -57,8 +322,140 @@ mod tests {
 
     #[test]
     fn test_get_provier_unknown_provider() {
-        let provider = get_provider("NXPROVIDER");
+        let provider = find_provider("src/tests/conf", NO_SYSTEM_DIR, "NXPROVIDER").unwrap();
         assert_eq!(provider.is_none(), true);
     }
 
+    #[test]
+    fn test_get_provider_google_offline() {
+        let provider = known_providers().remove("google").unwrap();
+        let params = provider.authorize_params.unwrap();
+        assert_eq!(params.get("access_type"), Some(&"offline".to_string()));
+        assert_eq!(params.get("prompt"), Some(&"consent".to_string()));
+        assert_eq!(provider.revocation_url,
+                   Some("https://oauth2.googleapis.com/revoke".to_string()));
+    }
+
+    #[test]
+    fn test_get_provider_from_registry() {
+        let provider = find_provider("src/tests/providers", NO_SYSTEM_DIR, "corp")
+            .unwrap()
+            .unwrap();
+        assert_eq!(provider.name, "Corp SSO");
+        assert_eq!(provider.scope, Some("openid profile".to_string()));
+        assert_eq!(provider.token_endpoint_auth_method,
+                   Some("client_secret_basic".to_string()));
+        let params = provider.authorize_params.unwrap();
+        assert_eq!(params.get("audience"), Some(&"https://api.corp.example".to_string()));
+
+        // the registry overrides the built-in providers
+        let provider = find_provider("src/tests/providers", NO_SYSTEM_DIR, "google")
+            .unwrap()
+            .unwrap();
+        assert_eq!(provider.name, "google");
+        assert_eq!(provider.scope, Some("email".to_string()));
+        assert_eq!(provider.endpoints(&HttpSettings::default()),
+                   Ok(("https://accounts.google.com/o/oauth2/v2/auth".to_string(),
+                       "https://oauth2.googleapis.com/token".to_string())));
+    }
+
+    #[test]
+    fn test_find_provider_from_system_dir() {
+        let provider = find_provider("src/tests/conf", "src/tests/providers", "corp");
+        assert_eq!(provider.unwrap().unwrap().name, "Corp SSO");
+
+        // the registry of the config dir overrides the one of the system dir
+        let rnd: String = thread_rng().gen_ascii_chars().take(10).collect();
+        let tmpdir = format!("/tmp/test-bearer-{}", rnd);
+        fs::create_dir_all(tmpdir.as_str()).unwrap();
+        fs::write(format!("{}/{}", tmpdir, PROVIDERS_FILE),
+                  "[corp]\nname = \"Team SSO\"\n\
+discovery_url = \"https://sso.team.example/.well-known/openid-configuration\"\n")
+            .unwrap();
+        let provider = find_provider(tmpdir.as_str(), "src/tests/providers", "corp");
+        assert_eq!(provider.unwrap().unwrap().name, "Team SSO");
+
+        fs::remove_dir_all(tmpdir).unwrap();
+    }
+
+    #[test]
+    fn test_provider_template() {
+        let provider = find_provider("src/tests/conf", NO_SYSTEM_DIR, "keycloak").unwrap().unwrap();
+        assert_eq!(provider.placeholders(),
+                   vec!["domain".to_string(), "realm".to_string()]);
+
+        let mut values = HashMap::new();
+        values.insert("domain".to_string(), "sso.example.com".to_string());
+        assert_eq!(provider.with_values(&values).unwrap_err(),
+                   BearerError::ValueError("".to_string()));
+
+        values.insert("realm".to_string(), "staff".to_string());
+        let provider = provider.with_values(&values).unwrap();
+        assert_eq!(provider.placeholders().len(), 0);
+        assert_eq!(provider.endpoints(&HttpSettings::default()),
+                   Ok(("https://sso.example.com/realms/staff/protocol/openid-connect/auth"
+                           .to_string(),
+                       "https://sso.example.com/realms/staff/protocol/openid-connect/token"
+                           .to_string())));
+
+        let provider = find_provider("src/tests/conf", NO_SYSTEM_DIR, "keycloak").unwrap().unwrap();
+        values.insert("domain".to_string(), "sso.example.com:8443".to_string());
+        let provider = provider.with_values(&values).unwrap();
+        assert_eq!(provider.token_url,
+                   Some("https://sso.example.com:8443/realms/staff/protocol/openid-connect/token"
+                       .to_string()));
+    }
+
+    #[test]
+    fn test_provider_template_invalid_values() {
+        let provider = find_provider("src/tests/conf", NO_SYSTEM_DIR, "azure").unwrap().unwrap();
+        for tenant in &["evil.example/x", "x?y", "x#", "user@evil.example", "a b", "%2e%2e",
+                        "..", ".", "", "host:443"] {
+            let mut values = HashMap::new();
+            values.insert("tenant".to_string(), tenant.to_string());
+            assert_eq!(provider.with_values(&values).unwrap_err(),
+                       BearerError::ValueError("".to_string()));
+        }
+
+        let mut values = HashMap::new();
+        values.insert("tenant".to_string(), "contoso.onmicrosoft.com".to_string());
+        let provider = provider.with_values(&values).unwrap();
+        assert_eq!(provider.token_url,
+                   Some("https://login.microsoftonline.com/contoso.onmicrosoft.com/oauth2/v2.0/\
+token"
+                       .to_string()));
+    }
+
+    #[test]
+    fn test_provider_endpoints_discovery() {
+        let (server_port, servhandler) = serve(move |server| {
+            let mut stream = server.incoming().next().unwrap().unwrap();
+            let mut buffer = [0; 4096];
+            stream.read(&mut buffer[..]).unwrap();
+            let request = String::from_utf8_lossy(&buffer).to_string();
+            assert!(request.starts_with("GET /.well-known/openid-configuration HTTP/1.1\r\n"));
+
+            let document = r#"{"authorization_endpoint": "https://idp/auth",
+"token_endpoint": "https://idp/oauth/token"}"#;
+            let content_len = format!("Content-Length: {}", document.len());
+            let resp = vec!["HTTP/1.0 200 Ok",
+                            "Content-Type: application/json",
+                            content_len.as_str(),
+                            "",
+                            document];
+            stream.write(resp.join("\r\n").as_bytes()).unwrap();
+        });
+
+        let provider = Provider {
+            name: "idp".to_string(),
+            token_url: Some("https://idp/token".to_string()),
+            discovery_url: Some(format!("http://127.0.0.1:{}/.well-known/openid-configuration",
+                                        server_port)),
+            ..Provider::default()
+        };
+        assert_eq!(provider.endpoints(&HttpSettings::default()),
+                   Ok(("https://idp/auth".to_string(), "https://idp/token".to_string())));
+        servhandler.join().unwrap();
+    }
+
 }
@@ -0,0 +1,265 @@
+//! Obtain a valid access token for a registered client.
+//!
+//! Expired tokens are refreshed, and the tokens of clients configured with a
+//! token exchange are exchanged from the token of their upstream client.
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+
+use super::super::config::{Config, TokenExchange, TokenKey, Tokens};
+use super::super::results::{BearerResult, BearerError};
+use super::oauth2client;
+use super::oauth2client::{TokenExchangeRequest, ACCESS_TOKEN_TYPE};
+
+/// Longest chain of token exchanges.
+const MAX_CHAIN_LENGTH: usize = 8;
+
+
+fn read_token_file(path: &str) -> BearerResult<String> {
+    let mut buf = String::new();
+    let read = if path == "-" {
+        io::stdin().read_to_string(&mut buf)
+    } else {
+        File::open(path).and_then(|mut file| file.read_to_string(&mut buf))
+    };
+    if let Err(err) = read {
+        return Err(BearerError::IOError(format!("Cannot read token from {}: {}", path, err)));
+    }
+    let token = buf.trim();
+    if token.is_empty() {
+        return Err(BearerError::ValueError(format!("No token found in {}", path)));
+    }
+    Ok(token.to_string())
+}
+
+
+fn refresh(conf: &Config, client_name: &str, key: &TokenKey) -> BearerResult<Tokens> {
+    debug!("Refreshing Token");
+    let rtoken = match conf.refresh_token() {
+        Some(rtoken) => rtoken,
+        None if key.is_default() && conf.access_token().is_none() => {
+            return Err(BearerError::ValueError("Client must be refreshed".to_string()));
+        }
+        None => {
+            return Err(BearerError::ValueError("Client must be refreshed. (No Refresh \
+ Token)".to_string()))
+        }
+    };
+    match oauth2client::from_refresh_token(&conf.client(), rtoken, key) {
+        Ok(tokens) => Ok(tokens),
+        Err(BearerError::OAuth2Error(ref err)) if err.error == "invalid_grant" => {
+            Err(BearerError::ValueError(format!("The refresh token has been rejected ({}). Run \
+`bearer refresh {}` to authorize the client again.",
+                                                err,
+                                                client_name)))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+
+fn exchange(config_dir: &str,
+            profile: Option<&str>,
+            conf: &Config,
+            exchange: &TokenExchange,
+            key: &TokenKey,
+            chain: &mut Vec<String>)
+            -> BearerResult<Tokens> {
+    debug!("Exchanging Token");
+    let subject_token = match (exchange.subject_client.as_ref(),
+                               exchange.subject_token_file.as_ref()) {
+        (Some(client), _) => {
+            resolve(config_dir, client.as_str(), profile, &TokenKey::default(), chain)?
+        }
+        (None, Some(path)) => read_token_file(path.as_str())?,
+        (None, None) => {
+            return Err(BearerError::ValueError("token_exchange requires a subject_client or a \
+subject_token_file"
+                .to_string()))
+        }
+    };
+    let actor_token = match exchange.actor_client {
+        Some(ref client) => {
+            Some(resolve(config_dir, client.as_str(), profile, &TokenKey::default(), chain)?)
+        }
+        None => None,
+    };
+
+    let client = conf.client();
+    let request = TokenExchangeRequest {
+        subject_token: subject_token.as_str(),
+        subject_token_type: exchange.subject_token_type
+            .as_ref()
+            .map(|val| val.as_str())
+            .unwrap_or(ACCESS_TOKEN_TYPE),
+        actor_token: actor_token.as_ref().map(|val| val.as_str()),
+        actor_token_type: exchange.actor_token_type.as_ref().map(|val| val.as_str()),
+        audience: key.audience.or(exchange.audience.as_ref().map(|val| val.as_str())),
+        resource: key.resource,
+        scope: key.scope.or(client.scope),
+        requested_token_type: exchange.requested_token_type.as_ref().map(|val| val.as_str()),
+    };
+    oauth2client::from_token_exchange(&client, &request)
+}
+
+
+fn resolve(config_dir: &str,
+           client_name: &str,
+           profile: Option<&str>,
+           key: &TokenKey,
+           chain: &mut Vec<String>)
+           -> BearerResult<String> {
+    if chain.iter().any(|name| name == client_name) || chain.len() >= MAX_CHAIN_LENGTH {
+        chain.push(client_name.to_string());
+        return Err(BearerError::ValueError(format!("Invalid chain of token exchanges: {}",
+                                                   chain.join(" -> "))));
+    }
+    chain.push(client_name.to_string());
+
+    let mut conf = Config::from_file(config_dir, client_name)?;
+    if let Some(profile) = profile {
+        // Upstream clients follow the selected profile when they define it.
+        if chain.len() == 1 || conf.profiles().contains(&profile) {
+            conf.select_profile(profile)?;
+        }
+    }
+    let expired = conf.tokens_for(key).map(|tokens| tokens.expired());
+    if expired == Some(false) {
+        debug!("Access Token Is OK");
+    } else {
+        let token_exchange = conf.client().token_exchange.cloned();
+        let tokens = match token_exchange {
+            Some(ref token_exchange) => {
+                exchange(config_dir, profile, &conf, token_exchange, key, chain)?
+            }
+            None => refresh(&conf, client_name, key)?,
+        };
+        conf.set_tokens_for(key, tokens);
+        conf.write()?;
+    }
+
+    chain.pop();
+    Ok(conf.tokens_for(key).unwrap().access_token.clone())
+}
+
+
+/// Return a valid access token for the client, refreshing or exchanging it if needed.
+pub fn valid_access_token(config_dir: &str,
+                          client_name: &str,
+                          profile: Option<&str>,
+                          key: &TokenKey)
+                          -> BearerResult<String> {
+    resolve(config_dir, client_name, profile, key, &mut Vec::new())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::prelude::*;
+    use rand::{thread_rng, Rng};
+
+    use super::*;
+    use super::super::super::test_server::serve;
+
+    fn register(config_dir: &str,
+                client_name: &str,
+                token_url: &str,
+                token_exchange: Option<TokenExchange>)
+                -> Config {
+        let mut conf = Config::new(config_dir,
+                                   client_name,
+                                   "provider",
+                                   "authorize_url",
+                                   token_url,
+                                   "client_id",
+                                   "secret",
+                                   None)
+            .unwrap();
+        conf.set_token_exchange(token_exchange);
+        conf
+    }
+
+    #[test]
+    fn test_valid_access_token_not_expired() {
+        let token = valid_access_token("src/tests/conf",
+                                       "dummy_with_tokens",
+                                       None,
+                                       &TokenKey::default());
+        assert_eq!(token, Ok("56afe18".to_string()));
+    }
+
+    #[test]
+    fn test_valid_access_token_exchange_loop() {
+        let rnd: String = thread_rng().gen_ascii_chars().take(10).collect();
+        let tmpdir = format!("/tmp/test-bearer-{}", rnd);
+
+        for &(name, upstream) in [("a", "b"), ("b", "a")].iter() {
+            let exchange = TokenExchange {
+                subject_client: Some(upstream.to_string()),
+                ..TokenExchange::default()
+            };
+            register(tmpdir.as_str(), name, "http://127.0.0.1:1/token", Some(exchange))
+                .write()
+                .unwrap();
+        }
+
+        let err = valid_access_token(tmpdir.as_str(), "a", None, &TokenKey::default())
+            .unwrap_err();
+        match err {
+            BearerError::ValueError(ref msg) => assert!(msg.ends_with("a -> b -> a")),
+            _ => panic!("Unexpected error {:?}", err),
+        }
+        fs::remove_dir_all(tmpdir).unwrap();
+    }
+
+    #[test]
+    fn test_valid_access_token_exchange() {
+        let rnd: String = thread_rng().gen_ascii_chars().take(10).collect();
+        let tmpdir = format!("/tmp/test-bearer-{}", rnd);
+
+        let (server_port, authservhandler) = serve(move |authorization_server| {
+            let mut stream = authorization_server.incoming().next().unwrap().unwrap();
+            let mut buffer = [0; 4096];
+            stream.read(&mut buffer[..]).unwrap();
+            let request = String::from_utf8_lossy(&buffer).to_string();
+            assert!(request.contains("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3A\
+token-exchange&subject_token=ssotok&"));
+            assert!(request.contains("&audience=billing"));
+
+            let tokens = r#"{"access_token": "billingtok", "expires_in": 60,
+"issued_token_type": "urn:ietf:params:oauth:token-type:access_token"}"#;
+            let content_len = format!("Content-Length: {}", tokens.len());
+            let resp = vec!["HTTP/1.0 200 Ok",
+                            "Content-Type: application/json",
+                            content_len.as_str(),
+                            "",
+                            tokens];
+            stream.write(resp.join("\r\n").as_bytes()).unwrap();
+        });
+        let token_url = format!("http://127.0.0.1:{}/token", server_port);
+
+        let mut sso = register(tmpdir.as_str(), "sso", token_url.as_str(), None);
+        sso.set_tokens(Tokens::new("ssotok", 3600, None));
+        sso.write().unwrap();
+        let exchange = TokenExchange {
+            subject_client: Some("sso".to_string()),
+            audience: Some("billing".to_string()),
+            ..TokenExchange::default()
+        };
+        register(tmpdir.as_str(), "billing", token_url.as_str(), Some(exchange))
+            .write()
+            .unwrap();
+
+
+        let token = valid_access_token(tmpdir.as_str(), "billing", None, &TokenKey::default());
+        assert_eq!(token, Ok("billingtok".to_string()));
+        authservhandler.join().unwrap();
+
+        // cached now
+        let token = valid_access_token(tmpdir.as_str(), "billing", None, &TokenKey::default());
+        assert_eq!(token, Ok("billingtok".to_string()));
+        fs::remove_dir_all(tmpdir).unwrap();
+    }
+}
@@ -0,0 +1,60 @@
+//! # Bearer
+//!
+//! Library behind the bearer command line utility. It reads the clients
+//! registered with `bearer register` and keeps their tokens valid, so
+//! Rust programs can share `~/.config/bearer` with the command line.
+//!
+//! ```no_run
+//! extern crate bearer;
+//!
+//! let token = bearer::access_token("my-client-name").unwrap();
+//! println!("Authorization: Bearer {}", token);
+//! ```
+//!
+
+#[macro_use]
+extern crate log;
+
+extern crate libc;
+extern crate tilde_expand;
+extern crate toml;
+extern crate chrono;
+
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+extern crate url;
+extern crate rustls;
+extern crate webpki;
+extern crate webpki_roots;
+extern crate serde_json;
+extern crate base64;
+extern crate ring;
+extern crate untrusted;
+
+#[cfg(test)]
+extern crate rand;
+
+pub mod config;
+pub mod helpers;
+pub mod results;
+#[cfg(test)]
+mod test_server;
+
+pub use config::{Config, ClientEdit, ClientRef, TokenExchange, TokenKey, Tokens};
+pub use helpers::http::HttpSettings;
+pub use helpers::oauth2client::{from_authcode, from_refresh_token, from_token_exchange,
+                                introspect_token, revoke_token, TokenExchangeRequest,
+                                TokenIntrospection};
+pub use helpers::path::default_config_dir;
+pub use helpers::tokens::valid_access_token;
+pub use results::{BearerError, BearerResult, OAuth2ErrorResponse};
+
+
+/// Return a valid access token for a client of the default config dir.
+///
+/// The token is refreshed, or exchanged, and saved if it has expired.
+pub fn access_token(client_name: &str) -> BearerResult<String> {
+    valid_access_token(default_config_dir().as_str(), client_name, None, &TokenKey::default())
+}
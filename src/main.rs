@@ -9,9 +9,6 @@ extern crate log;
 extern crate pretty_env_logger;
 
 extern crate clap;
-extern crate libc;
-extern crate tilde_expand;
-extern crate toml;
 extern crate chrono;
 
 extern crate serde;
@@ -19,17 +16,18 @@ extern crate serde;
 extern crate serde_derive;
 
 extern crate url;
-extern crate cabot;
 extern crate serde_json;
+extern crate ring;
 
-#[cfg(test)]
-extern crate rand;
+extern crate bearer;
 
 use std::io::Write;
-mod helpers;
-mod config;
+
+use bearer::results::BearerError;
+
 mod commands;
-mod results;
+#[cfg(test)]
+mod test_server;
 
 
 fn main() {
@@ -38,11 +36,15 @@ fn main() {
         Ok(()) => {
             debug!("Command bearer ended succesfully");
         }
-        Err(results::BearerError::ValueError(msg)) => {
+        Err(BearerError::ValueError(msg)) => {
             let _ = writeln!(&mut std::io::stderr(), "ERROR: {}", msg);
             std::process::exit(1);
         }
-        Err(results::BearerError::IOError(err)) => {
+        Err(BearerError::OAuth2Error(err)) => {
+            let _ = writeln!(&mut std::io::stderr(), "ERROR: {}", err);
+            std::process::exit(1);
+        }
+        Err(BearerError::IOError(err)) => {
             let _ = writeln!(&mut std::io::stderr(), "ERROR: {}", err);
             std::process::exit(1);
         }
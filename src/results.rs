@@ -1,10 +1,91 @@
 use std::error::Error;
 use std::fmt;
+#[cfg(test)]
+use std::mem;
+
+use serde_json;
+
+
+/// Error returned by an OAuth2.0 Authorization Server, see RFC 6749 section 5.2.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAuth2ErrorResponse {
+    pub error: String,
+    pub error_description: Option<String>,
+    pub error_uri: Option<String>,
+    /// HTTP status of the response, not set for errors received on the callback.
+    #[serde(skip_deserializing)]
+    pub status: Option<usize>,
+}
+
+impl OAuth2ErrorResponse {
+    pub fn new(error: &str, error_description: Option<&str>) -> Self {
+        OAuth2ErrorResponse {
+            error: error.to_string(),
+            error_description: error_description.map(|desc| desc.to_string()),
+            error_uri: None,
+            status: None,
+        }
+    }
+
+    /// Parse the body of a token endpoint response, return None if it is not an error.
+    pub fn from_json(status: usize, body: &str) -> Option<Self> {
+        match serde_json::from_str::<OAuth2ErrorResponse>(body) {
+            Ok(mut err) => {
+                err.status = Some(status);
+                Some(err)
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// Build an error from an http response that does not follow the RFC.
+    pub fn from_unexpected_response(status: usize, body: &str) -> Self {
+        let mut description = body.trim().to_string();
+        if description.len() > 200 {
+            let mut end = 200;
+            while !description.is_char_boundary(end) {
+                end -= 1;
+            }
+            description.truncate(end);
+            description.push_str("...");
+        }
+        OAuth2ErrorResponse {
+            error: "unexpected_response".to_string(),
+            error_description: Some(description),
+            error_uri: None,
+            status: Some(status),
+        }
+    }
+}
+
+impl fmt::Display for OAuth2ErrorResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let Some(ref description) = self.error_description {
+            write!(f, ": {}", description)?;
+        }
+        if let Some(ref uri) = self.error_uri {
+            write!(f, " (see {})", uri)?;
+        }
+        if let Some(status) = self.status {
+            write!(f, " [HTTP {}]", status)?;
+        }
+        Ok(())
+    }
+}
+
+
+impl Error for OAuth2ErrorResponse {
+    fn description(&self) -> &str {
+        self.error.as_str()
+    }
+}
+
 
 #[derive(Debug, Clone)]
 pub enum BearerError {
     ValueError(String),
-    OAuth2Error(String),
+    OAuth2Error(OAuth2ErrorResponse),
     IOError(String),
     ParseError(String),
     SerializationError(String),
@@ -16,7 +97,14 @@ pub type BearerResult<T> = Result<T, BearerError>;
 
 impl fmt::Display for BearerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match *self {
+            BearerError::OAuth2Error(ref err) => write!(f, "{}", err),
+            BearerError::ValueError(ref msg) |
+            BearerError::IOError(ref msg) |
+            BearerError::ParseError(ref msg) |
+            BearerError::SerializationError(ref msg) |
+            BearerError::UTF8EncodingError(ref msg) => write!(f, "{}", msg),
+        }
     }
 }
 
@@ -34,14 +122,60 @@ impl Error for BearerError {
     }
 
     fn cause(&self) -> Option<&Error> {
-        None  // TOFIX
+        match *self {
+            BearerError::OAuth2Error(ref err) => Some(err),
+            _ => None,
+        }
     }
 }
 
 
+/// Errors of the same kind are equal, their messages are not compared.
 #[cfg(test)]
 impl PartialEq for BearerError {
     fn eq(&self, other: &BearerError) -> bool {
-        self.description() == other.description()
+        mem::discriminant(self) == mem::discriminant(other)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oauth2_error_from_json() {
+        let err = OAuth2ErrorResponse::from_json(400,
+                                                 r#"{"error": "invalid_grant",
+"error_description": "Refresh token expired"}"#)
+            .unwrap();
+        assert_eq!(err.error, "invalid_grant");
+        assert_eq!(err.error_description, Some("Refresh token expired".to_string()));
+        assert_eq!(err.status, Some(400));
+        assert_eq!(format!("{}", err),
+                   "invalid_grant: Refresh token expired [HTTP 400]");
+    }
+
+    #[test]
+    fn test_oauth2_error_from_json_not_an_error() {
+        let err = OAuth2ErrorResponse::from_json(500, "<html>Internal Server Error</html>");
+        assert_eq!(err.is_none(), true);
+        let err = OAuth2ErrorResponse::from_unexpected_response(500,
+                                                                "<html>Internal Server \
+Error</html>");
+        assert_eq!(format!("{}", err),
+                   "unexpected_response: <html>Internal Server Error</html> [HTTP 500]");
+    }
+
+    #[test]
+    fn test_bearer_error() {
+        let err = BearerError::OAuth2Error(OAuth2ErrorResponse::new("invalid_grant", None));
+        assert_eq!(err.cause().map(|cause| cause.to_string()),
+                   Some("invalid_grant".to_string()));
+        assert_eq!(BearerError::IOError("a".to_string()).cause().is_none(), true);
+
+        assert_eq!(BearerError::IOError("a".to_string()),
+                   BearerError::IOError("b".to_string()));
+        assert!(BearerError::IOError("a".to_string()) != BearerError::ValueError("a".to_string()));
     }
 }
@@ -0,0 +1,22 @@
+//! Fake servers of the tests.
+
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use std::thread::JoinHandle;
+
+
+/// Run a server in a thread, on a free port of localhost.
+///
+/// Returns once the server listens, with its port and the thread to join.
+pub fn serve<F>(server: F) -> (u16, JoinHandle<()>)
+    where F: FnOnce(TcpListener) + Send + 'static
+{
+    let (ready, listening) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        ready.send(listener.local_addr().unwrap().port()).unwrap();
+        server(listener)
+    });
+    (listening.recv().unwrap(), handle)
+}